@@ -0,0 +1,137 @@
+mod cache_front;
+mod cache_options;
+mod cache_store;
+
+pub use cache_options::CacheOptions;
+pub use cache_store::Cache;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::{cache_front::CacheFront, Cache, CacheOptions};
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Session {
+        user: String,
+        visits: u32,
+    }
+
+    #[tokio::test]
+    async fn entries() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = crate::storage::StorageCollection::state_index(new_db.clone()).await?;
+        let cache = Cache::new(&collection, &CacheOptions::default());
+        let session = Session {
+            user: "ada".to_string(),
+            visits: 3,
+        };
+
+        cache.set("session", &session).await?;
+
+        assert_eq!(cache.get::<Session>("session").await?, Some(session));
+        assert_eq!(cache.get::<Session>("missing").await?, None);
+        assert!(cache.get::<u32>("session").await.is_err());
+
+        // namespaces keep the same key apart
+        let counters = cache.namespace("counters");
+
+        counters.set("session", &1).await?;
+
+        assert_eq!(counters.get::<u32>("session").await?, Some(1));
+        assert!(cache.get::<Session>("session").await?.is_some());
+        assert_eq!(counters.clear().await?, 1);
+        assert!(cache.get::<Session>("session").await?.is_some());
+
+        // entries outlive the process that set them
+        let reopened = Cache::new(
+            &crate::storage::StorageCollection::connect(new_db).await?,
+            &CacheOptions::default(),
+        );
+
+        assert_eq!(
+            reopened.get::<Session>("session").await?.map(|s| s.visits),
+            Some(3)
+        );
+        assert!(reopened.delete("session").await?);
+        assert!(!reopened.delete("session").await?);
+        assert_eq!(reopened.get::<Session>("session").await?, None);
+
+        // expired entries are never returned, and sweeping removes them
+        cache.set_for("brief", "gone", Duration::ZERO).await?;
+        cache
+            .set_for("lasting", "here", Duration::from_secs(3600))
+            .await?;
+
+        assert_eq!(cache.get::<String>("brief").await?, None);
+        assert_eq!(cache.sweep().await?, 1);
+        assert_eq!(
+            cache.get::<String>("lasting").await?.as_deref(),
+            Some("here")
+        );
+
+        let expiring = Cache::new(
+            &collection,
+            &CacheOptions {
+                default_ttl_seconds: Some(0),
+                ..Default::default()
+            },
+        );
+
+        expiring.set("default", &true).await?;
+
+        assert_eq!(expiring.get::<bool>("default").await?, None);
+
+        // memory is only trusted for the front TTL, since other processes write too
+        let here = Cache::new(
+            &collection,
+            &CacheOptions {
+                front_ttl_seconds: 0,
+                ..Default::default()
+            },
+        );
+        let elsewhere = Cache::new(&collection, &CacheOptions::default());
+
+        here.set("shared", &1).await?;
+        elsewhere.set("shared", &2).await?;
+
+        assert_eq!(here.get::<u32>("shared").await?, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let now = chrono::Utc::now();
+        let key = |name: &str| ("default".to_string(), name.to_string());
+        let mut front = CacheFront::new(2);
+
+        front.insert(key("a"), "1".to_string(), None);
+        front.insert(key("b"), "2".to_string(), None);
+
+        // reading `a` makes `b` the one to go
+        assert_eq!(front.get(&key("a"), now).as_deref(), Some("1"));
+
+        front.insert(key("c"), "3".to_string(), None);
+
+        assert_eq!(front.get(&key("b"), now), None);
+        assert_eq!(front.get(&key("a"), now).as_deref(), Some("1"));
+        assert_eq!(front.get(&key("c"), now).as_deref(), Some("3"));
+
+        front.insert(key("d"), "4".to_string(), Some(now));
+
+        assert_eq!(front.get(&key("d"), now), None);
+
+        let mut disabled = CacheFront::new(0);
+
+        disabled.insert(key("a"), "1".to_string(), None);
+
+        assert_eq!(disabled.get(&key("a"), now), None);
+    }
+}
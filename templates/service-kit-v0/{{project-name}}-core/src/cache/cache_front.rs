@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+
+/// A cache entry's namespace and key.
+pub type CacheKey = (String, String);
+
+#[derive(Clone, Debug)]
+struct FrontEntry {
+    /// The value as serialized JSON, the same as in the database.
+    value: String,
+    expires_at: Option<DateTime<Utc>>,
+    /// When the entry was last read or written, on the front's own clock.
+    used: u64,
+}
+
+impl FrontEntry {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// The most recently used cache entries, held in memory in front of the
+/// database. It only sees writes made through this process, so it isn't kept
+/// in step with other processes sharing the database; entries are held for a
+/// limited time instead.
+#[derive(Debug, Default)]
+pub struct CacheFront {
+    capacity: usize,
+    entries: HashMap<CacheKey, FrontEntry>,
+    /// Every entry's key by when it was last used, least recent first.
+    recency: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl CacheFront {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// The value held for `key`, unless it's expired by `now`.
+    pub fn get(&mut self, key: &CacheKey, now: DateTime<Utc>) -> Option<String> {
+        if self.entries.get(key)?.is_expired(now) {
+            self.remove(key);
+
+            return None;
+        }
+
+        let used = self.tick();
+        let entry = self.entries.get_mut(key)?;
+
+        self.recency.remove(&entry.used);
+        self.recency.insert(used, key.clone());
+        entry.used = used;
+
+        Some(entry.value.clone())
+    }
+
+    /// Hold `value` for `key`, making room by dropping the least recently
+    /// used entries.
+    pub fn insert(&mut self, key: CacheKey, value: String, expires_at: Option<DateTime<Utc>>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+
+            self.entries.remove(&oldest);
+        }
+
+        let used = self.tick();
+
+        self.recency.insert(used, key.clone());
+        self.entries.insert(
+            key,
+            FrontEntry {
+                value,
+                expires_at,
+                used,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+
+    /// Drop every entry in `namespace`.
+    pub fn remove_namespace(&mut self, namespace: &str) {
+        self.retain(|(entry_namespace, _), _| entry_namespace != namespace);
+    }
+
+    /// Drop every entry expired by `now`.
+    pub fn remove_expired(&mut self, now: DateTime<Utc>) {
+        self.retain(|_, entry| !entry.is_expired(now));
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&CacheKey, &FrontEntry) -> bool) {
+        let recency = &mut self.recency;
+
+        self.entries.retain(|key, entry| {
+            let kept = keep(key, entry);
+
+            if !kept {
+                recency.remove(&entry.used);
+            }
+
+            kept
+        });
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// How the cache behaves, from the `[cache]` section of the configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CacheOptions {
+    /// How many entries are also kept in memory, dropping the least recently
+    /// used first. Zero reads everything from the database.
+    #[serde(default = "CacheOptions::default_capacity")]
+    pub capacity: usize,
+    /// How many seconds an entry held in memory is used before it's read from
+    /// the database again. Memory only sees this process's writes, so changes
+    /// made by other processes, such as a separate worker, can take this long
+    /// to show up.
+    #[serde(default = "CacheOptions::default_front_ttl_seconds")]
+    pub front_ttl_seconds: u64,
+    /// How long entries set without a TTL of their own last. Unset, they
+    /// last until deleted.
+    pub default_ttl_seconds: Option<u64>,
+    /// How many seconds the server waits between deleting expired entries.
+    /// Expired entries are never returned either way; sweeping frees the space.
+    #[serde(default = "CacheOptions::default_sweep_every_seconds")]
+    pub sweep_every_seconds: u64,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: Self::default_capacity(),
+            front_ttl_seconds: Self::default_front_ttl_seconds(),
+            default_ttl_seconds: None,
+            sweep_every_seconds: Self::default_sweep_every_seconds(),
+        }
+    }
+}
+
+impl CacheOptions {
+    fn default_capacity() -> usize {
+        1024
+    }
+
+    fn default_front_ttl_seconds() -> u64 {
+        5
+    }
+
+    fn default_sweep_every_seconds() -> u64 {
+        60
+    }
+
+    pub fn default_ttl(&self) -> Option<Duration> {
+        self.default_ttl_seconds.map(Duration::from_secs)
+    }
+
+    pub fn front_ttl(&self) -> Duration {
+        Duration::from_secs(self.front_ttl_seconds)
+    }
+
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_secs(self.sweep_every_seconds.max(1))
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    cache_front::{CacheFront, CacheKey},
+    CacheOptions,
+};
+use crate::shutdown::Shutdown;
+use crate::storage::{sql_timestamp, StorageCollection};
+
+/// Where entries go when no namespace is picked.
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// A durable key-value cache kept in the state database, with the most
+/// recently used entries also held in memory for a short while. Values are
+/// anything serde can turn into JSON. Keys are kept apart by namespace, and
+/// clones share both the database and the entries held in memory. Other
+/// processes using the same database have memory of their own, so their
+/// writes can take up to the front TTL to be seen here.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    pool: sqlx::SqlitePool,
+    front: Arc<Mutex<CacheFront>>,
+    namespace: String,
+    default_ttl: Option<Duration>,
+    front_ttl: Duration,
+}
+
+impl Cache {
+    /// A cache in `collection`'s database, which has to be migrated.
+    pub fn new(collection: &StorageCollection, options: &CacheOptions) -> Self {
+        Self {
+            pool: collection.pool.clone(),
+            front: Arc::new(Mutex::new(CacheFront::new(options.capacity))),
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            default_ttl: options.default_ttl(),
+            front_ttl: options.front_ttl(),
+        }
+    }
+
+    /// The same cache, but reading and writing only the keys in `namespace`.
+    pub fn namespace(&self, namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            ..self.clone()
+        }
+    }
+
+    /// The value at `key`, unless there's none or it has expired.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> crate::Result<Option<T>> {
+        let key = self.key(key);
+        let now = Utc::now();
+
+        if let Some(value) = self.front().get(&key, now) {
+            return Ok(Some(serde_json::from_str(&value)?));
+        }
+
+        let row: Option<(String, Option<DateTime<Utc>>)> = sqlx::query_as(
+            "select value, expires_at from cache_entries where namespace = $1 and key = $2 and (expires_at is null or expires_at > $3)",
+        )
+        .bind(&key.0)
+        .bind(&key.1)
+        .bind(sql_timestamp(now))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((value, expires_at)) = row else {
+            return Ok(None);
+        };
+        let parsed = serde_json::from_str(&value)?;
+
+        self.hold(key, value, expires_at);
+
+        Ok(Some(parsed))
+    }
+
+    /// Store `value` at `key` for the configured default TTL, replacing
+    /// whatever was there.
+    pub async fn set<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> crate::Result<()> {
+        self.put(key, value, self.default_ttl).await
+    }
+
+    /// Store `value` at `key` until `ttl` has passed, replacing whatever was
+    /// there.
+    pub async fn set_for<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Duration,
+    ) -> crate::Result<()> {
+        self.put(key, value, Some(ttl)).await
+    }
+
+    /// Delete the entry at `key`, returning whether there was one.
+    pub async fn delete(&self, key: &str) -> crate::Result<bool> {
+        let key = self.key(key);
+        let deleted = sqlx::query("delete from cache_entries where namespace = $1 and key = $2")
+            .bind(&key.0)
+            .bind(&key.1)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        self.front().remove(&key);
+
+        Ok(deleted > 0)
+    }
+
+    /// Delete every entry in this namespace, returning how many there were.
+    pub async fn clear(&self) -> crate::Result<u64> {
+        let deleted = sqlx::query("delete from cache_entries where namespace = $1")
+            .bind(&self.namespace)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        self.front().remove_namespace(&self.namespace);
+
+        Ok(deleted)
+    }
+
+    /// Delete the expired entries in every namespace, returning how many
+    /// there were.
+    pub async fn sweep(&self) -> crate::Result<u64> {
+        let now = Utc::now();
+        let swept = sqlx::query("delete from cache_entries where expires_at <= $1")
+            .bind(sql_timestamp(now))
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        self.front().remove_expired(now);
+
+        Ok(swept)
+    }
+
+    /// Sweep every `interval` until shutdown begins. Failures are logged and
+    /// retried at the next sweep.
+    pub async fn sweep_on_schedule(&self, interval: Duration, shutdown: &Shutdown) {
+        let mut interval = tokio::time::interval(interval);
+
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.wait() => return,
+            }
+
+            match self.sweep().await {
+                Ok(0) => {}
+                Ok(swept) => tracing::debug!("Swept {} expired cache entries", swept),
+                Err(error) => tracing::error!("Cache sweep failed: {}", error),
+            }
+        }
+    }
+
+    async fn put<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> crate::Result<()> {
+        let key = self.key(key);
+        let value = serde_json::to_string(value)?;
+        // a TTL too long to represent never expires
+        let expires_at = ttl
+            .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+            .and_then(|ttl| Utc::now().checked_add_signed(ttl));
+
+        sqlx::query(
+            "insert into cache_entries (namespace, key, value, expires_at) values ($1, $2, $3, $4) on conflict (namespace, key) do update set value = excluded.value, expires_at = excluded.expires_at",
+        )
+        .bind(&key.0)
+        .bind(&key.1)
+        .bind(&value)
+        .bind(expires_at.map(sql_timestamp))
+        .execute(&self.pool)
+        .await?;
+
+        self.hold(key, value, expires_at);
+
+        Ok(())
+    }
+
+    /// Hold `value` in memory until it expires or the front TTL has passed,
+    /// whichever comes first.
+    fn hold(&self, key: CacheKey, value: String, expires_at: Option<DateTime<Utc>>) {
+        let trusted_until = chrono::Duration::from_std(self.front_ttl)
+            .ok()
+            .and_then(|ttl| Utc::now().checked_add_signed(ttl));
+
+        self.front().insert(
+            key,
+            value,
+            expires_at.into_iter().chain(trusted_until).min(),
+        );
+    }
+
+    fn key(&self, key: &str) -> CacheKey {
+        (self.namespace.clone(), key.to_string())
+    }
+
+    fn front(&self) -> MutexGuard<'_, CacheFront> {
+        self.front
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -1,4 +1,13 @@
+use futures::{Stream, StreamExt, TryStreamExt};
+use indicatif::ProgressBar;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
 use crate::settings::NetworkSettings;
+use crate::storage::{
+    StorageEntry, StorageEvent, StorageFileAttributes, StorageFileMeta, StorageFileVersion,
+    StoragePage, StorageSearchHit, StorageSyncAction, StorageSyncOptions, StorageSyncPlan,
+};
 use {{crate_name}}_proto::prelude::*;
 
 /// Make a network request with a `NetworkSettings` configuration against the /health endpoint.
@@ -10,18 +19,387 @@ pub async fn health(config: NetworkSettings) -> crate::Result<HealthCheckRespons
     Ok(response.json::<HealthCheckResponse>().await?)
 }
 
-pub struct WebClient;
+/// Percent-encode one segment of a URL path, so characters such as `#`, `?`,
+/// `%` and `/` reach the server as part of the name.
+fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Percent-encode each segment of a stored path, without its leading separator.
+fn encode_path(path: &str) -> String {
+    path.trim_start_matches('/')
+        .split('/')
+        .map(encode_segment)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub struct WebClient {
+    config: NetworkSettings,
+    http: reqwest::Client,
+}
 
 impl WebClient {
     pub fn new() -> Self {
-        Self
-    }
-
-    pub async fn health(&self) -> crate::Result<HealthCheckResponse> {
-        health(NetworkSettings {
+        Self::with_settings(NetworkSettings {
             host: "localhost".to_string(),
             port: 8080,
         })
-        .await
+    }
+
+    pub fn with_settings(config: NetworkSettings) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn file_url(&self, path: &str) -> String {
+        format!(
+            "http://{}/files/{}",
+            self.config.address(),
+            encode_path(path)
+        )
+    }
+
+    fn versions_url(&self, path: &str) -> String {
+        format!(
+            "http://{}/versions/{}",
+            self.config.address(),
+            encode_path(path)
+        )
+    }
+
+    pub async fn health(&self) -> crate::Result<HealthCheckResponse> {
+        health(self.config.clone()).await
+    }
+
+    async fn list_page<T: serde::de::DeserializeOwned>(
+        &self,
+        prefix: &str,
+        recursive: bool,
+        cursor: Option<String>,
+    ) -> crate::Result<StoragePage<T>> {
+        let mut query = vec![
+            ("prefix", prefix.to_string()),
+            ("recursive", recursive.to_string()),
+        ];
+
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        let response = self
+            .http
+            .get(format!("http://{}/files", self.config.address()))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    async fn list_all<T: serde::de::DeserializeOwned>(
+        &self,
+        prefix: &str,
+        recursive: bool,
+    ) -> crate::Result<Vec<T>> {
+        let mut items = vec![];
+        let mut cursor = None;
+
+        loop {
+            let page = self.list_page(prefix, recursive, cursor).await?;
+
+            items.extend(page.items);
+
+            match page.cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// The immediate children of a stored directory.
+    pub async fn list_files(&self, prefix: &str) -> crate::Result<Vec<StorageEntry>> {
+        self.list_all(prefix, false).await
+    }
+
+    /// Every stored file below a prefix.
+    pub async fn walk_files(&self, prefix: &str) -> crate::Result<Vec<StorageFileMeta>> {
+        self.list_all(prefix, true).await
+    }
+
+    /// Stream a stored file, or one of its previous versions, into the local
+    /// file `output`, advancing `progress` as bytes arrive. `output` is only
+    /// created once the server has found the file.
+    pub async fn download_file(
+        &self,
+        path: &str,
+        version: Option<i64>,
+        output: &Path,
+        progress: &ProgressBar,
+    ) -> crate::Result<()> {
+        let mut request = self.http.get(self.file_url(path));
+
+        if let Some(version) = version {
+            request = request.query(&[("version", version)]);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+
+        if let Some(length) = response.content_length() {
+            progress.set_length(length);
+        }
+
+        let mut writer = tokio::fs::File::create(output).await?;
+        let mut chunks = response.bytes_stream();
+
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+
+            writer.write_all(&chunk).await?;
+            progress.inc(chunk.len() as u64);
+        }
+
+        writer.flush().await?;
+        progress.finish();
+
+        Ok(())
+    }
+
+    /// Upload a local file, advancing `progress` as bytes are sent. If the
+    /// server already has the same contents, they're linked instead of sent.
+    /// Empty `attributes` leave those of an existing file as they were.
+    pub async fn upload_file(
+        &self,
+        local: &Path,
+        remote: &str,
+        attributes: &StorageFileAttributes,
+        progress: &ProgressBar,
+    ) -> crate::Result<StorageFileMeta> {
+        let hash = crate::storage::file_hash(local).await?;
+        let linked = self
+            .upload_request(remote, attributes)
+            .query(&[("hash", &hash)])
+            .send()
+            .await?;
+
+        if linked.status().is_success() {
+            progress.finish();
+
+            return Ok(linked.json().await?);
+        }
+
+        let file = tokio::fs::File::open(local).await?;
+
+        progress.set_length(file.metadata().await?.len());
+
+        let chunks = tokio_util::io::ReaderStream::new(file).inspect_ok({
+            let progress = progress.clone();
+
+            move |chunk| progress.inc(chunk.len() as u64)
+        });
+
+        let response = self
+            .upload_request(remote, attributes)
+            .body(reqwest::Body::wrap_stream(chunks))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        progress.finish();
+
+        Ok(response.json().await?)
+    }
+
+    /// A PUT to `remote` carrying `attributes` as headers.
+    fn upload_request(
+        &self,
+        remote: &str,
+        attributes: &StorageFileAttributes,
+    ) -> reqwest::RequestBuilder {
+        let mut request = self.http.put(self.file_url(remote));
+
+        if let Some(content_type) = &attributes.content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+
+        for (key, value) in &attributes.metadata {
+            request = request.header(format!("x-meta-{}", key), value);
+        }
+
+        if !attributes.tags.is_empty() {
+            let tags = attributes.tags.iter().cloned().collect::<Vec<_>>();
+
+            request = request.header("x-tags", tags.join(","));
+        }
+
+        request
+    }
+
+    /// The stored text files containing every word of `query`, best matches first.
+    pub async fn search_files(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> crate::Result<Vec<StorageSearchHit>> {
+        Ok(self
+            .http
+            .get(format!("http://{}/files/search", self.config.address()))
+            .query(&[("q", query), ("limit", &limit.to_string())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Changes to stored files at or below `prefix` as they're made, read
+    /// from the server-sent events of `/files/watch`. The stream ends when
+    /// the server closes it, such as when this client fell too far behind.
+    pub async fn watch_files(
+        &self,
+        prefix: &str,
+    ) -> crate::Result<impl Stream<Item = crate::Result<StorageEvent>>> {
+        let response = self
+            .http
+            .get(format!("http://{}/files/watch", self.config.address()))
+            .query(&[("prefix", prefix)])
+            .send()
+            .await?
+            .error_for_status()?;
+        let lines = tokio_util::io::StreamReader::new(
+            response.bytes_stream().map_err(std::io::Error::other),
+        )
+        .lines();
+
+        Ok(futures::stream::try_unfold(lines, |mut lines| async move {
+            while let Some(line) = lines.next_line().await? {
+                // events are a single `data` line; keep-alives and separators are skipped
+                if let Some(data) = line.strip_prefix("data:") {
+                    return Ok(Some((serde_json::from_str(data.trim_start())?, lines)));
+                }
+            }
+
+            Ok(None)
+        }))
+    }
+
+    /// Every stored file carrying `tag`.
+    pub async fn tagged_files(&self, tag: &str) -> crate::Result<Vec<StorageFileMeta>> {
+        Ok(self
+            .http
+            .get(format!(
+                "http://{}/tags/{}",
+                self.config.address(),
+                encode_segment(tag)
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    pub async fn remove_file(&self, path: &str) -> crate::Result<()> {
+        self.http
+            .delete(self.file_url(path))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// The kept versions of a stored file, newest first.
+    pub async fn file_versions(&self, path: &str) -> crate::Result<Vec<StorageFileVersion>> {
+        Ok(self
+            .http
+            .get(self.versions_url(path))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Make a previous version of a stored file current again.
+    pub async fn restore_file(&self, path: &str, version: i64) -> crate::Result<StorageFileMeta> {
+        Ok(self
+            .http
+            .post(self.versions_url(path))
+            .query(&[("version", version)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Work out what `apply_sync` would need to do to make the files stored
+    /// under `prefix` match the local directory `local`.
+    pub async fn sync_plan(
+        &self,
+        local: &Path,
+        prefix: &str,
+        options: &StorageSyncOptions,
+    ) -> crate::Result<StorageSyncPlan> {
+        let remote = self.walk_files(prefix).await?;
+
+        StorageSyncPlan::build(local, &prefix.parse()?, remote, options).await
+    }
+
+    /// Carry out a sync plan, advancing `progress` once per action.
+    pub async fn apply_sync(
+        &self,
+        plan: &StorageSyncPlan,
+        progress: &ProgressBar,
+    ) -> crate::Result<()> {
+        progress.set_length(plan.actions.len() as u64);
+
+        for action in &plan.actions {
+            progress.set_message(action.to_string());
+
+            match action {
+                StorageSyncAction::Create { local, remote }
+                | StorageSyncAction::Update { local, remote } => {
+                    self.upload_file(
+                        local,
+                        remote,
+                        &StorageFileAttributes::default(),
+                        &ProgressBar::hidden(),
+                    )
+                    .await?;
+                }
+                StorageSyncAction::Delete { remote } => self.remove_file(remote).await?,
+            }
+
+            progress.inc(1);
+        }
+
+        progress.finish_and_clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(encode_path("/a b/c#1?x=%2"), "a%20b/c%231%3Fx%3D%252");
+        assert_eq!(encode_path("/docs/naïve.txt"), "docs/na%C3%AFve.txt");
+        assert_eq!(encode_segment("a/b"), "a%2Fb");
     }
 }
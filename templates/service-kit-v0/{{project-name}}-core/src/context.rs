@@ -1,8 +1,24 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use crate::storage::StorageBackend;
+
 #[derive(Clone, Debug)]
 pub struct WebContext {
     pub settings: crate::settings::Settings,
     pub network: crate::settings::NetworkSettings,
-    pub storage: crate::storage::StorageCollection,
+    pub storage: crate::storage::Storage,
+    pub cache: crate::cache::Cache,
+    pub queue: crate::queue::Queue,
+    pub scheduler: crate::scheduler::Scheduler,
+    /// Tells background tasks when the server is stopping.
+    pub shutdown: crate::shutdown::Shutdown,
+    /// Where the cache, queue and scheduler keep their state, which is the
+    /// storage database itself with the sqlite backend unless configured
+    /// otherwise.
+    database: crate::storage::StorageCollection,
+    /// Background work that has to wind down before the context is closed.
+    tasks: Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>,
 }
 
 impl WebContext {
@@ -10,13 +26,69 @@ impl WebContext {
         network: crate::settings::NetworkSettings,
         settings: crate::settings::Settings,
     ) -> crate::Result<Self> {
+        let storage = settings.storage().await?;
+        let database = settings.database(&storage).await?;
+
         Ok(Self {
             network,
-            storage: crate::storage::StorageCollection::file_index(settings.storage_path()).await?,
+            storage,
+            cache: settings.cache(&database),
+            queue: settings.queue(&database),
+            scheduler: settings.scheduler(&database),
+            shutdown: settings.shutdown(),
+            database,
+            tasks: Arc::default(),
             settings,
         })
     }
 
+    /// Run `task` in the background until the context is drained. It should
+    /// end soon after shutdown begins.
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.tasks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(tokio::spawn(task));
+    }
+
+    /// Wait for every background task to end once shutdown begins. Those
+    /// still running after the drain timeout are aborted.
+    pub async fn drain_tasks(&self) {
+        let tasks = std::mem::take(
+            &mut *self
+                .tasks
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        let aborts: Vec<_> = tasks.iter().map(|task| task.abort_handle()).collect();
+
+        if self
+            .shutdown
+            .drain(futures::future::join_all(tasks))
+            .await
+            .is_none()
+        {
+            tracing::warn!(
+                "Background tasks were still running after the drain timeout, stopping anyway"
+            );
+
+            aborts.iter().for_each(tokio::task::AbortHandle::abort);
+        }
+    }
+
+    /// Close every database connection. Nothing should use the context
+    /// afterwards.
+    pub async fn close(&self) {
+        self.storage.close().await;
+        self.database.close().await;
+    }
+
+    /// Store the files of `seed` that haven't been seeded before, recording
+    /// them in the state database.
+    pub async fn seed(&self, seed: &crate::storage::StorageSeed) -> crate::Result<Vec<String>> {
+        crate::storage::seed(&*self.storage, &self.database, seed).await
+    }
+
     pub async fn listener(&self) -> crate::Result<tokio::net::TcpListener> {
         self.network.listener().await
     }
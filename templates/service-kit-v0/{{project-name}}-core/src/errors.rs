@@ -12,8 +12,16 @@ pub enum Error {
     SerializationError(#[from] serde_json::Error),
     #[error("Storage error: {0}")]
     StorageError(#[from] crate::storage::StorageError),
+    #[error("Queue error: {0}")]
+    QueueError(#[from] crate::queue::QueueError),
+    #[error("Scheduler error: {0}")]
+    SchedulerError(#[from] crate::scheduler::SchedulerError),
+    #[error("Archive error: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
     #[error("Storage not configured, unable to initialize storage collection")]
     StorageNotConfiguredError,
+    #[error("Seeding was asked for, but no storage seed is configured")]
+    SeedNotConfiguredError,
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("Unable to get next terminal event")]
@@ -28,4 +36,79 @@ pub enum Error {
 
     #[error("Unable to parse selected option: {0}")]
     CliOptionSelectError(#[from] strum::ParseError),
+
+    #[error("Multipart error: {0}")]
+    MultipartError(#[from] axum::extract::multipart::MultipartError),
+    #[error("Batch request is missing its `{0}` part")]
+    MissingBatchPart(String),
+}
+
+impl Error {
+    /// The HTTP status an error is reported with when it reaches an API handler.
+    pub fn status_code(&self) -> axum::http::StatusCode {
+        use crate::storage::StorageError;
+        use axum::http::StatusCode;
+
+        match self {
+            Error::StorageError(error) => match error {
+                StorageError::MissingFileName
+                | StorageError::InvalidFileName
+                | StorageError::MissingPathData
+                | StorageError::BadPath(_)
+                | StorageError::EmptyPath
+                | StorageError::PathEscapesRoot(_)
+                | StorageError::PathTooLong { .. }
+                | StorageError::NameTooLong { .. }
+                | StorageError::InvalidPathCharacter { .. }
+                | StorageError::NonUtf8Path(_)
+                | StorageError::InvalidArchive(_)
+                | StorageError::ArchiveMismatch(_)
+                | StorageError::MoveIntoSelf { .. } => StatusCode::BAD_REQUEST,
+                StorageError::FileNotFound(_)
+                | StorageError::DirectoryNotFound(_)
+                | StorageError::VersionNotFound { .. }
+                | StorageError::UnknownContent(_) => StatusCode::NOT_FOUND,
+                StorageError::FileExists(_) => StatusCode::CONFLICT,
+                StorageError::FileTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                StorageError::QuotaExceeded { .. } | StorageError::FileLimitReached { .. } => {
+                    StatusCode::INSUFFICIENT_STORAGE
+                }
+                StorageError::AttributesUnsupported
+                | StorageError::SearchUnsupported
+                | StorageError::WatchUnsupported
+                | StorageError::MaintenanceUnsupported => StatusCode::NOT_IMPLEMENTED,
+                StorageError::IncompleteContents(_)
+                | StorageError::BackupFailed(_)
+                | StorageError::IntegrityCheckFailed(_)
+                | StorageError::MigrationChecksumMismatch { .. }
+                | StorageError::UnknownMigration(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Error::QueueError(error) => match error {
+                crate::queue::QueueError::ClaimLost(_) => StatusCode::CONFLICT,
+                crate::queue::QueueError::DeadLetterNotFound(_) => StatusCode::NOT_FOUND,
+            },
+            Error::SchedulerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::SqlxError(sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            Error::SerializationError(_)
+            | Error::MultipartError(_)
+            | Error::MissingBatchPart(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl axum::response::IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status_code();
+
+        if status.is_server_error() {
+            tracing::error!("{}", self);
+        }
+
+        (
+            status,
+            axum::Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
 }
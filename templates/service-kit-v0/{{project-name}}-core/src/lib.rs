@@ -1,9 +1,13 @@
+mod cache;
 mod client;
 mod context;
 mod errors;
+mod queue;
+mod scheduler;
 mod server;
 mod service;
 mod settings;
+mod shutdown;
 mod storage;
 mod telemetry;
 mod tui;
@@ -0,0 +1,167 @@
+mod queue_error;
+mod queue_job;
+mod queue_options;
+mod queue_store;
+mod queue_worker;
+
+pub use queue_error::QueueError;
+pub use queue_job::{QueueDeadLetter, QueueJob};
+pub use queue_options::QueueOptions;
+pub use queue_store::Queue;
+pub use queue_worker::Worker;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tempfile::{tempdir, TempDir};
+
+    use super::{Queue, QueueError, QueueJob, QueueOptions, Worker};
+
+    /// A queue in a fresh database, which lasts as long as the directory.
+    async fn queue(options: QueueOptions) -> Result<(Queue, TempDir), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = crate::storage::StorageCollection::state_index(new_db).await?;
+
+        Ok((Queue::new(&collection, &options), temp_dir))
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Email {
+        to: String,
+    }
+
+    #[tokio::test]
+    async fn jobs() -> Result<(), Box<dyn std::error::Error>> {
+        let (queue, _dir) = queue(QueueOptions::default()).await?;
+        let email = Email {
+            to: "ada@example.com".to_string(),
+        };
+
+        let id = queue.enqueue("emails", &email).await?;
+        queue
+            .enqueue_in("emails", &email, Duration::from_secs(3600))
+            .await?;
+
+        assert_eq!(queue.len("emails").await?, 2);
+        assert_eq!(queue.len("reindex").await?, 0);
+        assert!(queue.dequeue("reindex").await?.is_none());
+
+        let job = queue.dequeue("emails").await?.expect("Job was not due");
+
+        assert_eq!((job.id, job.attempts), (id, 1));
+        assert_eq!(job.payload_as::<Email>()?, email);
+
+        // the claimed job is hidden, and the delayed one isn't due yet
+        assert!(queue.dequeue("emails").await?.is_none());
+
+        queue.complete(&job).await?;
+
+        assert_eq!(queue.len("emails").await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retries() -> Result<(), Box<dyn std::error::Error>> {
+        let (queue, _dir) = queue(QueueOptions {
+            visibility_timeout_seconds: 0,
+            max_attempts: 2,
+            backoff_seconds: 0,
+            ..Default::default()
+        })
+        .await?;
+
+        queue.enqueue("reindex", "/docs").await?;
+
+        // a claim that times out lets another worker have the job
+        let stale = queue.dequeue("reindex").await?.expect("Job was not due");
+        let job = queue
+            .dequeue("reindex")
+            .await?
+            .expect("Job was not handed out again");
+
+        assert_eq!((stale.id, job.attempts), (job.id, 2));
+        assert!(matches!(
+            queue.complete(&stale).await,
+            Err(crate::Error::QueueError(QueueError::ClaimLost(_)))
+        ));
+
+        // out of attempts, the job is moved to the dead letters
+        queue.fail(&job, "index locked").await?;
+
+        assert_eq!(queue.len("reindex").await?, 0);
+
+        let dead = queue.dead_letters("reindex").await?;
+
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].last_error.as_deref(), Some("index locked"));
+        assert_eq!(dead[0].attempts, 2);
+
+        let requeued = queue.requeue(dead[0].id).await?;
+        let job = queue
+            .dequeue("reindex")
+            .await?
+            .expect("Job was not requeued");
+
+        assert_eq!((job.id, job.attempts), (requeued, 1));
+        assert_eq!(job.payload_as::<String>()?, "/docs");
+        assert!(queue.dead_letters("reindex").await?.is_empty());
+        assert!(matches!(
+            queue.requeue(dead[0].id).await,
+            Err(crate::Error::QueueError(QueueError::DeadLetterNotFound(_)))
+        ));
+
+        let backoff = QueueOptions {
+            backoff_seconds: 2,
+            max_backoff_seconds: 10,
+            ..Default::default()
+        };
+
+        assert_eq!(backoff.backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff.backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff.backoff(80), Duration::from_secs(10));
+
+        Ok(())
+    }
+
+    struct FlakyWorker;
+
+    #[async_trait::async_trait]
+    impl Worker for FlakyWorker {
+        fn queue(&self) -> &str {
+            "flaky"
+        }
+
+        async fn run(&self, job: &QueueJob) -> crate::Result<()> {
+            match job.attempts {
+                1 => Err(crate::Error::StorageNotConfiguredError),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn workers() -> Result<(), Box<dyn std::error::Error>> {
+        let (queue, _dir) = queue(QueueOptions {
+            backoff_seconds: 0,
+            ..Default::default()
+        })
+        .await?;
+
+        queue.enqueue("flaky", &()).await?;
+
+        assert!(queue.work_once(&FlakyWorker).await?);
+        assert_eq!(queue.len("flaky").await?, 1);
+        assert!(queue.work_once(&FlakyWorker).await?);
+        assert_eq!(queue.len("flaky").await?, 0);
+        assert!(!queue.work_once(&FlakyWorker).await?);
+        assert!(queue.dead_letters("flaky").await?.is_empty());
+
+        Ok(())
+    }
+}
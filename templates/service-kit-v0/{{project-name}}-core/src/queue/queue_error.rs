@@ -0,0 +1,7 @@
+#[derive(Debug, thiserror::Error)]
+pub enum QueueError {
+    #[error("job {0} was handed out again after its visibility timeout")]
+    ClaimLost(i64),
+    #[error("dead letter not found: {0}")]
+    DeadLetterNotFound(i64),
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+
+/// A job handed out by the queue. It stays claimed by whoever dequeued it
+/// until it's completed or failed, or until the visibility timeout passes.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct QueueJob {
+    pub id: i64,
+    pub queue: String,
+    #[sqlx(json)]
+    pub payload: serde_json::Value,
+    /// How many times the job has been handed out, including this one.
+    pub attempts: i64,
+    pub max_attempts: i64,
+    /// Why the previous attempt failed, if one did.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl QueueJob {
+    pub fn payload_as<T: DeserializeOwned>(&self) -> crate::Result<T> {
+        Ok(serde_json::from_value(self.payload.clone())?)
+    }
+
+    /// Whether failing this attempt sends the job to the dead letters.
+    pub fn is_last_attempt(&self) -> bool {
+        self.attempts >= self.max_attempts
+    }
+}
+
+/// A job that failed every attempt it was allowed.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct QueueDeadLetter {
+    pub id: i64,
+    pub queue: String,
+    #[sqlx(json)]
+    pub payload: serde_json::Value,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub failed_at: DateTime<Utc>,
+}
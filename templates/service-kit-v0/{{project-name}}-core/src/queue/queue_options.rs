@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// How the queue behaves, from the `[queue]` section of the configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct QueueOptions {
+    /// How many seconds a worker has to finish a job before it's handed to
+    /// another worker.
+    #[serde(default = "QueueOptions::default_visibility_timeout_seconds")]
+    pub visibility_timeout_seconds: u64,
+    /// How many times a job is tried before it goes to the dead letters.
+    #[serde(default = "QueueOptions::default_max_attempts")]
+    pub max_attempts: u32,
+    /// How many seconds a failed job waits before its first retry. Each
+    /// retry after that waits twice as long as the one before.
+    #[serde(default = "QueueOptions::default_backoff_seconds")]
+    pub backoff_seconds: u64,
+    /// The longest a failed job waits between retries, in seconds.
+    #[serde(default = "QueueOptions::default_max_backoff_seconds")]
+    pub max_backoff_seconds: u64,
+    /// How many milliseconds an idle worker waits before checking its queue again.
+    #[serde(default = "QueueOptions::default_poll_every_millis")]
+    pub poll_every_millis: u64,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            visibility_timeout_seconds: Self::default_visibility_timeout_seconds(),
+            max_attempts: Self::default_max_attempts(),
+            backoff_seconds: Self::default_backoff_seconds(),
+            max_backoff_seconds: Self::default_max_backoff_seconds(),
+            poll_every_millis: Self::default_poll_every_millis(),
+        }
+    }
+}
+
+impl QueueOptions {
+    fn default_visibility_timeout_seconds() -> u64 {
+        30
+    }
+
+    fn default_max_attempts() -> u32 {
+        5
+    }
+
+    fn default_backoff_seconds() -> u64 {
+        1
+    }
+
+    fn default_max_backoff_seconds() -> u64 {
+        3600
+    }
+
+    fn default_poll_every_millis() -> u64 {
+        1000
+    }
+
+    pub fn visibility_timeout(&self) -> Duration {
+        Duration::from_secs(self.visibility_timeout_seconds)
+    }
+
+    /// How long to wait before retrying a job that has failed `attempts` times.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 1u64
+            .checked_shl(attempts.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+
+        Duration::from_secs(
+            self.backoff_seconds
+                .saturating_mul(factor)
+                .min(self.max_backoff_seconds),
+        )
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_every_millis.max(1))
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{QueueDeadLetter, QueueError, QueueJob, QueueOptions, Worker};
+use crate::shutdown::Shutdown;
+use crate::storage::{sql_timestamp, StorageCollection};
+
+/// `now` pushed on by `delay`, capped a thousand years out so that it still
+/// compares as text with other timestamps.
+fn after(now: DateTime<Utc>, delay: Duration) -> String {
+    let cap = chrono::Duration::weeks(52 * 1000);
+    let later = now + chrono::Duration::from_std(delay).unwrap_or(cap).min(cap);
+
+    sql_timestamp(later)
+}
+
+/// A durable job queue kept in the state database. Jobs are JSON payloads
+/// on named queues, handed out one worker at a time, and retried with
+/// exponential backoff until they succeed or run out of attempts.
+#[derive(Clone, Debug)]
+pub struct Queue {
+    pool: sqlx::SqlitePool,
+    options: Arc<QueueOptions>,
+}
+
+impl Queue {
+    /// A queue in `collection`'s database, which has to be migrated.
+    pub fn new(collection: &StorageCollection, options: &QueueOptions) -> Self {
+        Self {
+            pool: collection.pool.clone(),
+            options: Arc::new(options.clone()),
+        }
+    }
+
+    /// Add a job to `queue` to be handed out right away, returning its id.
+    pub async fn enqueue<T: Serialize + ?Sized>(
+        &self,
+        queue: &str,
+        payload: &T,
+    ) -> crate::Result<i64> {
+        self.enqueue_in(queue, payload, Duration::ZERO).await
+    }
+
+    /// Add a job to `queue` that isn't handed out until `delay` has passed,
+    /// returning its id.
+    pub async fn enqueue_in<T: Serialize + ?Sized>(
+        &self,
+        queue: &str,
+        payload: &T,
+        delay: Duration,
+    ) -> crate::Result<i64> {
+        let now = Utc::now();
+        let id = sqlx::query(
+            "insert into queue_jobs (queue, payload, max_attempts, visible_at, created_at) values ($1, $2, $3, $4, $5)",
+        )
+        .bind(queue)
+        .bind(serde_json::to_string(payload)?)
+        .bind(self.max_attempts())
+        .bind(after(now, delay))
+        .bind(sql_timestamp(now))
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(id)
+    }
+
+    /// Claim the next due job on `queue`, oldest first. It's handed out
+    /// again once the visibility timeout passes unless it's completed or
+    /// failed before then.
+    pub async fn dequeue(&self, queue: &str) -> crate::Result<Option<QueueJob>> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let job = sqlx::query_as(
+            r#"
+            update queue_jobs set attempts = attempts + 1, visible_at = $1
+            where id = (
+                select id from queue_jobs where queue = $2 and visible_at <= $3
+                order by visible_at, id limit 1
+            )
+            returning id, queue, payload, attempts, max_attempts, last_error, created_at
+            "#,
+        )
+        .bind(after(now, self.options.visibility_timeout()))
+        .bind(queue)
+        .bind(sql_timestamp(now))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(job)
+    }
+
+    /// Remove a finished job from the queue.
+    pub async fn complete(&self, job: &QueueJob) -> crate::Result<()> {
+        let deleted = sqlx::query("delete from queue_jobs where id = $1 and attempts = $2")
+            .bind(job.id)
+            .bind(job.attempts)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if deleted == 0 {
+            Err(QueueError::ClaimLost(job.id))?;
+        }
+
+        Ok(())
+    }
+
+    /// Record that `job` failed with `error`. It's retried after a backoff,
+    /// or moved to the dead letters if that was its last attempt.
+    pub async fn fail(&self, job: &QueueJob, error: &str) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let updated = if job.is_last_attempt() {
+            let moved = sqlx::query(
+                r#"
+                insert into queue_dead_letters
+                    (queue, payload, attempts, last_error, created_at, failed_at)
+                select queue, payload, attempts, $1, created_at, $4 from queue_jobs
+                where id = $2 and attempts = $3
+                "#,
+            )
+            .bind(error)
+            .bind(job.id)
+            .bind(job.attempts)
+            .bind(sql_timestamp(Utc::now()))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            sqlx::query("delete from queue_jobs where id = $1 and attempts = $2")
+                .bind(job.id)
+                .bind(job.attempts)
+                .execute(&mut *tx)
+                .await?;
+
+            moved
+        } else {
+            let attempts = u32::try_from(job.attempts).unwrap_or(u32::MAX);
+
+            sqlx::query(
+                "update queue_jobs set visible_at = $1, last_error = $2 where id = $3 and attempts = $4",
+            )
+            .bind(after(Utc::now(), self.options.backoff(attempts)))
+            .bind(error)
+            .bind(job.id)
+            .bind(job.attempts)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+        };
+
+        if updated == 0 {
+            Err(QueueError::ClaimLost(job.id))?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// How many jobs on `queue` are waiting or being worked on.
+    pub async fn len(&self, queue: &str) -> crate::Result<usize> {
+        let (count,): (i64,) = sqlx::query_as("select count(*) from queue_jobs where queue = $1")
+            .bind(queue)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count as usize)
+    }
+
+    /// The jobs on `queue` that ran out of attempts, oldest first.
+    pub async fn dead_letters(&self, queue: &str) -> crate::Result<Vec<QueueDeadLetter>> {
+        Ok(sqlx::query_as(
+            "select id, queue, payload, attempts, last_error, created_at, failed_at from queue_dead_letters where queue = $1 order by id",
+        )
+        .bind(queue)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Put a dead letter back on its queue as a new job with a fresh set of
+    /// attempts, returning the new job's id.
+    pub async fn requeue(&self, dead_letter: i64) -> crate::Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let found: Option<(String, String)> =
+            sqlx::query_as("select queue, payload from queue_dead_letters where id = $1")
+                .bind(dead_letter)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let (queue, payload) = found.ok_or(QueueError::DeadLetterNotFound(dead_letter))?;
+        let id = sqlx::query(
+            "insert into queue_jobs (queue, payload, max_attempts, visible_at, created_at) values ($1, $2, $3, $4, $4)",
+        )
+        .bind(queue)
+        .bind(payload)
+        .bind(self.max_attempts())
+        .bind(sql_timestamp(Utc::now()))
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        sqlx::query("delete from queue_dead_letters where id = $1")
+            .bind(dead_letter)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    /// Hand the next due job on `worker`'s queue to it, and complete or fail
+    /// the job depending on how it went. Returns whether there was a job.
+    pub async fn work_once(&self, worker: &dyn Worker) -> crate::Result<bool> {
+        let Some(job) = self.dequeue(worker.queue()).await? else {
+            return Ok(false);
+        };
+
+        match worker.run(&job).await {
+            Ok(()) => self.complete(&job).await?,
+            Err(error) => {
+                if job.is_last_attempt() {
+                    tracing::error!(
+                        "Job {} on {} failed its last attempt: {}",
+                        job.id,
+                        job.queue,
+                        error
+                    );
+                } else {
+                    tracing::warn!(
+                        "Job {} on {} failed attempt {}: {}",
+                        job.id,
+                        job.queue,
+                        job.attempts,
+                        error
+                    );
+                }
+
+                self.fail(&job, &error.to_string()).await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Keep handing jobs to `worker` until shutdown begins, checking its
+    /// queue again after the poll interval whenever it's empty. A job that's
+    /// underway is finished first.
+    pub async fn work(&self, worker: Arc<dyn Worker>, shutdown: &Shutdown) {
+        while !shutdown.is_triggered() {
+            match self.work_once(worker.as_ref()).await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(error) => tracing::error!("Worker on {} failed: {}", worker.queue(), error),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.options.poll_interval()) => {}
+                _ = shutdown.wait() => {}
+            }
+        }
+    }
+
+    fn max_attempts(&self) -> i64 {
+        i64::from(self.options.max_attempts.max(1))
+    }
+}
@@ -0,0 +1,15 @@
+use super::QueueJob;
+
+/// Something that handles the jobs on one queue. Register workers in
+/// `server::worker` to have `server worker` run them.
+#[async_trait::async_trait]
+pub trait Worker: Send + Sync {
+    /// The queue this worker takes jobs from.
+    fn queue(&self) -> &str;
+
+    /// Handle one job. An error sends the job back to be retried after a
+    /// backoff, or to the dead letters once it's out of attempts. A job that
+    /// runs past the visibility timeout may be handed to another worker too,
+    /// so handlers should be safe to run more than once.
+    async fn run(&self, job: &QueueJob) -> crate::Result<()>;
+}
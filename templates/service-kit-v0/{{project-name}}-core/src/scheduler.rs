@@ -0,0 +1,223 @@
+mod scheduler_error;
+mod scheduler_job;
+mod scheduler_options;
+mod scheduler_schedule;
+mod scheduler_store;
+
+pub use scheduler_error::SchedulerError;
+pub use scheduler_job::{ScheduledJob, ScheduledJobStatus};
+pub use scheduler_options::SchedulerOptions;
+pub use scheduler_schedule::JobSchedule;
+pub use scheduler_store::Scheduler;
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::{JobSchedule, ScheduledJob, Scheduler, SchedulerError, SchedulerOptions};
+
+    /// Counts its runs, failing every one if `fails` is set.
+    #[derive(Default)]
+    struct CountingJob {
+        runs: AtomicUsize,
+        fails: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl ScheduledJob for CountingJob {
+        fn name(&self) -> &str {
+            if self.fails {
+                "failing"
+            } else {
+                "counting"
+            }
+        }
+
+        fn schedule(&self) -> JobSchedule {
+            JobSchedule::Every(Duration::from_secs(3600))
+        }
+
+        async fn run(&self) -> crate::Result<()> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+
+            match self.fails {
+                true => Err(crate::Error::StorageNotConfiguredError),
+                false => Ok(()),
+            }
+        }
+    }
+
+    /// Takes longer to run than its lease lasts.
+    struct SlowJob;
+
+    #[async_trait::async_trait]
+    impl ScheduledJob for SlowJob {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn schedule(&self) -> JobSchedule {
+            JobSchedule::Every(Duration::from_secs(3600))
+        }
+
+        fn lease(&self) -> Duration {
+            Duration::from_millis(200)
+        }
+
+        async fn run(&self) -> crate::Result<()> {
+            tokio::time::sleep(Duration::from_millis(600)).await;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn schedules() -> Result<(), Box<dyn std::error::Error>> {
+        let now = chrono::Utc::now();
+        let every: JobSchedule = "every 90s".parse()?;
+
+        assert_eq!(
+            every.next_after(now),
+            Some(now + chrono::Duration::seconds(90))
+        );
+        assert_eq!(every.to_string(), "every 90s");
+        assert_eq!(
+            "every 2h".parse::<JobSchedule>()?.to_string(),
+            "every 7200s"
+        );
+
+        let hourly: JobSchedule = "0 0 * * * *".parse()?;
+        let next = hourly.next_after(now).expect("Cron schedule ended");
+
+        assert!(next > now && next - now <= chrono::Duration::hours(1));
+        assert_eq!(hourly.to_string(), "0 0 * * * *");
+
+        for invalid in ["every 0s", "every 5", "every 5w", "every soon", "0 0 *"] {
+            assert!(matches!(
+                invalid.parse::<JobSchedule>(),
+                Err(SchedulerError::InvalidSchedule { .. })
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_runs() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = crate::storage::StorageCollection::state_index(new_db).await?;
+        let job = Arc::new(CountingJob::default());
+        let failing = Arc::new(CountingJob {
+            fails: true,
+            ..Default::default()
+        });
+        // two instances sharing one database
+        let mut first = Scheduler::new(&collection, &SchedulerOptions::default());
+        let mut second = Scheduler::new(&collection, &SchedulerOptions::default());
+
+        first.register(job.clone())?;
+        first.register(failing.clone())?;
+        second.register(job.clone())?;
+
+        assert!(matches!(
+            first.register(job.clone()),
+            Err(crate::Error::SchedulerError(SchedulerError::DuplicateJob(
+                _
+            )))
+        ));
+
+        first.prepare().await?;
+        second.prepare().await?;
+
+        // nothing is due until an hour from now
+        assert!(!first.run_if_due(job.as_ref()).await?);
+
+        let due = || {
+            sqlx::query("update scheduled_jobs set next_run_at = '2000-01-01 00:00:00.000'")
+                .execute(&collection.pool)
+        };
+
+        due().await?;
+
+        // once due, whichever instance gets there first runs it, only once
+        assert!(second.run_if_due(job.as_ref()).await?);
+        assert!(!first.run_if_due(job.as_ref()).await?);
+        assert_eq!(job.runs.load(Ordering::SeqCst), 1);
+
+        // a lock left behind blocks others until it runs out
+        due().await?;
+        sqlx::query("update scheduled_jobs set locked_by = 'gone', locked_until = '9999-01-01 00:00:00.000'")
+            .execute(&collection.pool)
+            .await?;
+
+        assert!(!first.run_if_due(job.as_ref()).await?);
+
+        sqlx::query("update scheduled_jobs set locked_until = '2000-01-01 00:00:00.000'")
+            .execute(&collection.pool)
+            .await?;
+
+        assert!(first.run_if_due(job.as_ref()).await?);
+        assert!(first.run_if_due(failing.as_ref()).await?);
+
+        let statuses = first.statuses().await?;
+
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(
+            (
+                statuses[0].name.as_str(),
+                statuses[0].runs,
+                statuses[0].failures
+            ),
+            ("counting", 2, 0)
+        );
+        assert_eq!(statuses[0].schedule, "every 3600s");
+        assert!(statuses[0].locked_by.is_none());
+        assert!(statuses[0].next_run_at > chrono::Utc::now());
+        assert_eq!((statuses[1].runs, statuses[1].failures), (1, 1));
+        assert!(statuses[1].last_error.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lease_renewals() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = crate::storage::StorageCollection::state_index(new_db).await?;
+        let mut first = Scheduler::new(&collection, &SchedulerOptions::default());
+        let mut second = Scheduler::new(&collection, &SchedulerOptions::default());
+
+        first.register(Arc::new(SlowJob))?;
+        second.register(Arc::new(SlowJob))?;
+        first.prepare().await?;
+
+        sqlx::query("update scheduled_jobs set next_run_at = '2000-01-01 00:00:00.000'")
+            .execute(&collection.pool)
+            .await?;
+
+        // the run outlasts its first lease, but is still held when another
+        // instance looks after that lease would have run out
+        let (ran, taken) = tokio::join!(first.run_if_due(&SlowJob), async {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+
+            second.run_if_due(&SlowJob).await
+        });
+
+        assert!(ran?);
+        assert!(!taken?);
+        assert_eq!(first.statuses().await?[0].runs, 1);
+
+        Ok(())
+    }
+}
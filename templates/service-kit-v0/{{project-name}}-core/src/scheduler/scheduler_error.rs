@@ -0,0 +1,7 @@
+#[derive(Debug, thiserror::Error)]
+pub enum SchedulerError {
+    #[error("invalid schedule {schedule:?}: {reason}")]
+    InvalidSchedule { schedule: String, reason: String },
+    #[error("a job named {0} is already registered")]
+    DuplicateJob(String),
+}
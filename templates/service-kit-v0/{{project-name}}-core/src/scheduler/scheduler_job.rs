@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::JobSchedule;
+
+/// A task the server runs periodically. Register jobs in `server::scheduler`
+/// to have the full server run them.
+#[async_trait::async_trait]
+pub trait ScheduledJob: Send + Sync {
+    /// What the job is known as, which has to be unique. Its run history is
+    /// kept under this name.
+    fn name(&self) -> &str;
+
+    fn schedule(&self) -> JobSchedule;
+
+    /// How long a run holds the job before another instance may take it
+    /// over, in case this one died mid-run. The lease is renewed while the
+    /// run goes on, so it only runs out once the instance stops renewing it.
+    fn lease(&self) -> Duration {
+        Duration::from_secs(10 * 60)
+    }
+
+    /// Do the work. An error is recorded in the job's status; the job runs
+    /// again at its next scheduled time either way.
+    async fn run(&self) -> crate::Result<()>;
+}
+
+/// Where a scheduled job stands, as shown at `/admin/jobs`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, sqlx::FromRow)]
+pub struct ScheduledJobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub next_run_at: DateTime<Utc>,
+    /// The instance running the job right now, if one is.
+    pub locked_by: Option<String>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    /// Why the latest run failed, or nothing if it succeeded.
+    pub last_error: Option<String>,
+    pub runs: i64,
+    pub failures: i64,
+}
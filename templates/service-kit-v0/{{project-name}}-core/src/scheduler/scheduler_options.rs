@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// How the scheduler behaves, from the `[scheduler]` section of the
+/// configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SchedulerOptions {
+    /// Up to how many seconds each instance waits past a job's due time
+    /// before trying to take it, so that instances sharing a database don't
+    /// all reach for it at once.
+    #[serde(default = "SchedulerOptions::default_jitter_seconds")]
+    pub jitter_seconds: u64,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            jitter_seconds: Self::default_jitter_seconds(),
+        }
+    }
+}
+
+impl SchedulerOptions {
+    fn default_jitter_seconds() -> u64 {
+        5
+    }
+
+    /// A random wait of up to `jitter_seconds`.
+    pub fn jitter(&self) -> Duration {
+        let max = self.jitter_seconds.saturating_mul(1000).saturating_add(1);
+
+        Duration::from_millis(rand::random::<u64>() % max)
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::SchedulerError;
+
+/// When a job runs: on a cron expression, or at a fixed interval after each
+/// run. Parses from `every <n><s|m|h|d>`, such as `every 90s`, or from a
+/// cron expression with a seconds field, such as `0 30 4 * * *`, in UTC.
+#[derive(Clone, Debug)]
+pub enum JobSchedule {
+    Cron(Box<cron::Schedule>),
+    Every(Duration),
+}
+
+impl JobSchedule {
+    /// The first time the job is due after `now`, if it ever is again.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            JobSchedule::Cron(schedule) => schedule.after(&now).next(),
+            JobSchedule::Every(interval) => {
+                now.checked_add_signed(chrono::Duration::from_std(*interval).ok()?)
+            }
+        }
+    }
+
+    fn parse_interval(interval: &str) -> Option<Duration> {
+        let split = interval.find(|c: char| !c.is_ascii_digit())?;
+        let (count, unit) = interval.split_at(split);
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None,
+        };
+
+        Some(Duration::from_secs(
+            count.parse::<u64>().ok()?.checked_mul(seconds)?,
+        ))
+        .filter(|interval| !interval.is_zero())
+    }
+}
+
+impl std::str::FromStr for JobSchedule {
+    type Err = SchedulerError;
+
+    fn from_str(schedule: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| SchedulerError::InvalidSchedule {
+            schedule: schedule.to_string(),
+            reason,
+        };
+
+        match schedule.trim().strip_prefix("every ") {
+            Some(interval) => Self::parse_interval(interval.trim())
+                .map(JobSchedule::Every)
+                .ok_or_else(|| invalid("expected an interval like 30s, 5m, 1h or 1d".to_string())),
+            None => schedule
+                .parse::<cron::Schedule>()
+                .map(|schedule| JobSchedule::Cron(Box::new(schedule)))
+                .map_err(|error| invalid(error.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for JobSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobSchedule::Cron(schedule) => write!(f, "{}", schedule.source()),
+            JobSchedule::Every(interval) => write!(f, "every {}s", interval.as_secs()),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{ScheduledJob, ScheduledJobStatus, SchedulerError, SchedulerOptions};
+use crate::shutdown::Shutdown;
+use crate::storage::{sql_timestamp, StorageCollection};
+
+/// How long to wait before trying again when the scheduler's own database
+/// work fails.
+const RETRY_PAUSE: Duration = Duration::from_secs(30);
+
+/// The shortest wait between renewals of a running job's lease, however
+/// short the lease.
+const MIN_RENEWAL: Duration = Duration::from_millis(100);
+
+/// Stands in for the next run of a job that will never be due again.
+fn never() -> DateTime<Utc> {
+    NaiveDate::from_ymd_opt(9999, 12, 31)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .expect("Invalid far-future date")
+        .and_utc()
+}
+
+/// When a lease on `job` taken at `from` runs out.
+fn lease_end(job: &dyn ScheduledJob, from: DateTime<Utc>) -> DateTime<Utc> {
+    from.checked_add_signed(chrono::Duration::from_std(job.lease()).unwrap_or_default())
+        .unwrap_or_else(never)
+}
+
+/// Runs registered jobs on their schedules. Every instance sharing the
+/// state database agrees on when each job is next due through a row in
+/// `scheduled_jobs`, and only the instance that locks that row runs it.
+#[derive(Clone)]
+pub struct Scheduler {
+    pool: sqlx::SqlitePool,
+    options: Arc<SchedulerOptions>,
+    /// Who this instance is in the lock rows.
+    instance: String,
+    jobs: Vec<Arc<dyn ScheduledJob>>,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("instance", &self.instance)
+            .field(
+                "jobs",
+                &self.jobs.iter().map(|job| job.name()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Scheduler {
+    /// A scheduler in `collection`'s database, which has to be migrated.
+    pub fn new(collection: &StorageCollection, options: &SchedulerOptions) -> Self {
+        Self {
+            pool: collection.pool.clone(),
+            options: Arc::new(options.clone()),
+            instance: format!("{}-{:08x}", std::process::id(), rand::random::<u32>()),
+            jobs: vec![],
+        }
+    }
+
+    pub fn register(&mut self, job: Arc<dyn ScheduledJob>) -> crate::Result<()> {
+        if self
+            .jobs
+            .iter()
+            .any(|registered| registered.name() == job.name())
+        {
+            Err(SchedulerError::DuplicateJob(job.name().to_string()))?;
+        }
+
+        self.jobs.push(job);
+
+        Ok(())
+    }
+
+    /// Where every job that has ever been registered stands, by name.
+    pub async fn statuses(&self) -> crate::Result<Vec<ScheduledJobStatus>> {
+        Ok(sqlx::query_as(
+            "select name, schedule, next_run_at, locked_by, locked_until, last_started_at, last_finished_at, last_error, runs, failures from scheduled_jobs order by name",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Record each registered job's schedule. A job whose schedule hasn't
+    /// changed keeps its next run, so one that fell due while nothing was
+    /// running runs straight away.
+    pub async fn prepare(&self) -> crate::Result<()> {
+        let now = Utc::now();
+
+        for job in &self.jobs {
+            let schedule = job.schedule();
+
+            sqlx::query(
+                r#"
+                insert into scheduled_jobs (name, schedule, next_run_at) values ($1, $2, $3)
+                on conflict (name) do update set
+                    next_run_at = case when schedule = excluded.schedule then next_run_at else excluded.next_run_at end,
+                    schedule = excluded.schedule
+                "#,
+            )
+            .bind(job.name())
+            .bind(schedule.to_string())
+            .bind(sql_timestamp(schedule.next_after(now).unwrap_or_else(never)))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep running every registered job whenever it's due, until shutdown
+    /// begins. A job that's underway is finished first.
+    pub async fn run(self, shutdown: Shutdown) {
+        if self.jobs.is_empty() {
+            tracing::info!("No scheduled jobs are registered");
+
+            return;
+        }
+
+        futures::future::join_all(
+            self.jobs
+                .iter()
+                .map(|job| self.keep_running(job.as_ref(), &shutdown)),
+        )
+        .await;
+    }
+
+    /// Run `job` if it's due and no other instance holds it, returning
+    /// whether it ran. The lease is renewed halfway through each term for as
+    /// long as the run goes on. A failed run still counts; its error is
+    /// recorded.
+    pub async fn run_if_due(&self, job: &dyn ScheduledJob) -> crate::Result<bool> {
+        let started = Utc::now();
+        let claimed = sqlx::query(
+            r#"
+            update scheduled_jobs set locked_by = $1, locked_until = $2, last_started_at = $3
+            where name = $4 and next_run_at <= $3 and (locked_until is null or locked_until <= $3)
+            "#,
+        )
+        .bind(&self.instance)
+        .bind(sql_timestamp(lease_end(job, started)))
+        .bind(sql_timestamp(started))
+        .bind(job.name())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if claimed == 0 {
+            return Ok(false);
+        }
+
+        tracing::debug!("Running scheduled job {}", job.name());
+
+        let renew_every = (job.lease() / 2).max(MIN_RENEWAL);
+        let mut run = job.run();
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut run => break outcome,
+                _ = tokio::time::sleep(renew_every) => {
+                    if let Err(error) = self.renew(job).await {
+                        tracing::warn!("Unable to renew the lease on {}: {}", job.name(), error);
+                    }
+                }
+            }
+        };
+
+        let error = match outcome {
+            Ok(()) => None,
+            Err(error) => {
+                tracing::error!("Scheduled job {} failed: {}", job.name(), error);
+
+                Some(error.to_string())
+            }
+        };
+        let finished = Utc::now();
+        let next = job.schedule().next_after(finished).unwrap_or_else(never);
+
+        sqlx::query(
+            r#"
+            update scheduled_jobs set
+                locked_by = null, locked_until = null, last_finished_at = $1, last_error = $2,
+                next_run_at = $3, runs = runs + 1, failures = failures + $4
+            where name = $5 and locked_by = $6
+            "#,
+        )
+        .bind(sql_timestamp(finished))
+        .bind(&error)
+        .bind(sql_timestamp(next))
+        .bind(i64::from(error.is_some()))
+        .bind(job.name())
+        .bind(&self.instance)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(true)
+    }
+
+    /// Hold `job` for another full lease from now, as long as this instance
+    /// still holds it.
+    async fn renew(&self, job: &dyn ScheduledJob) -> crate::Result<()> {
+        sqlx::query(
+            "update scheduled_jobs set locked_until = $1 where name = $2 and locked_by = $3",
+        )
+        .bind(sql_timestamp(lease_end(job, Utc::now())))
+        .bind(job.name())
+        .bind(&self.instance)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn keep_running(&self, job: &dyn ScheduledJob, shutdown: &Shutdown) {
+        while !shutdown.is_triggered() {
+            let wait = match self.until_available(job.name()).await {
+                Ok(wait) => wait + self.options.jitter(),
+                Err(error) => {
+                    tracing::error!("Unable to schedule {}: {}", job.name(), error);
+
+                    RETRY_PAUSE
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = shutdown.wait() => return,
+            }
+
+            if let Err(error) = self.run_if_due(job).await {
+                tracing::error!("Unable to run scheduled job {}: {}", job.name(), error);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_PAUSE) => {}
+                    _ = shutdown.wait() => return,
+                }
+            }
+        }
+    }
+
+    /// How long until `name` is due and not held by anyone.
+    async fn until_available(&self, name: &str) -> crate::Result<Duration> {
+        let (next_run_at, locked_until): (DateTime<Utc>, Option<DateTime<Utc>>) =
+            sqlx::query_as("select next_run_at, locked_until from scheduled_jobs where name = $1")
+                .bind(name)
+                .fetch_one(&self.pool)
+                .await?;
+        let available = locked_until.map_or(next_run_at, |locked| locked.max(next_run_at));
+
+        Ok((available - Utc::now()).to_std().unwrap_or_default())
+    }
+}
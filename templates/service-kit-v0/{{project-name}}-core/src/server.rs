@@ -4,7 +4,9 @@ use axum::Router;
 
 pub mod api;
 pub mod full;
+pub mod scheduler;
 pub mod web;
+pub mod worker;
 
 pub mod protocol_service {
     pub struct ProtocolService;
@@ -20,19 +22,52 @@ pub mod protocol_service {
     }
 }
 
-pub async fn init(context: crate::WebContext) -> crate::Result<()> {
-    let app = Router::new()
-        .merge(web::router(context.clone()).await)
-        .merge(api::router(context.clone()).await)
-        .with_state(context.clone());
-
+/// Serve `app` until shutdown begins, then stop accepting connections and
+/// give in-flight requests and background tasks until the drain timeout to
+/// finish, all at once, before closing the context. Shutdown is triggered on
+/// the way out however serving ends, so that background tasks stop too.
+pub async fn serve(context: &crate::WebContext, app: Router) -> crate::Result<()> {
     let listener = context.listener().await?;
-
-    axum::serve(
+    let server = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await?;
+    .with_graceful_shutdown(context.shutdown.wait());
+    let serving = async {
+        let served = context.shutdown.drain(server).await;
+
+        context.shutdown.trigger();
+
+        served
+    };
+
+    let (served, ()) = tokio::join!(serving, context.drain_tasks());
+
+    context.close().await;
+
+    match served {
+        Some(served) => Ok(served?),
+        None => {
+            tracing::warn!("Requests were still running after the drain timeout, stopping anyway");
+
+            Ok(())
+        }
+    }
+}
+
+/// Everything the web and api servers serve, plus the status of the
+/// scheduled jobs only the full server runs.
+pub async fn router(context: crate::WebContext) -> Router<crate::WebContext> {
+    Router::new()
+        .merge(web::router(context.clone()).await)
+        .merge(api::router(context.clone()).await)
+        .merge(api::admin::router(context.clone()).await)
+}
+
+pub async fn init(context: crate::WebContext) -> crate::Result<()> {
+    let app = router(context.clone()).await.with_state(context.clone());
+
+    scheduler::start(&context).await?;
 
-    Ok(())
+    serve(&context, app).await
 }
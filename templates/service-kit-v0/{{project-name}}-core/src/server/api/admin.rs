@@ -0,0 +1,67 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use crate::scheduler::ScheduledJobStatus;
+
+pub async fn router(_context: crate::WebContext) -> Router<crate::WebContext> {
+    Router::new().route("/admin/jobs", get(jobs))
+}
+
+/// Where every scheduled job stands, including when it next runs and how
+/// its latest run went.
+#[tracing::instrument(level = "debug", skip(context))]
+async fn jobs(
+    State(context): State<crate::WebContext>,
+) -> crate::Result<Json<Vec<ScheduledJobStatus>>> {
+    Ok(Json(context.scheduler.statuses().await?))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    async fn status(app: axum::Router) -> StatusCode {
+        let request = Request::get("/admin/jobs")
+            .body(Body::empty())
+            .expect("Failed to build request");
+
+        app.oneshot(request)
+            .await
+            .expect("Router never fails")
+            .status()
+    }
+
+    #[tokio::test]
+    async fn jobs() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let settings = crate::settings::Settings {
+            cli: clap::Parser::parse_from(["test", "-a", "test", "debug"]),
+            config: serde_json::from_value(serde_json::json!({
+                "storage": { "backend": "memory" },
+                "database": { "path": temp_dir.path().join("state.db") },
+            }))?,
+        };
+        let network = crate::settings::NetworkSettings {
+            host: "localhost".to_string(),
+            port: 0,
+        };
+        let context = crate::WebContext::new(network, settings).await?;
+
+        // only the full server runs the scheduler, so only it reports on jobs
+        let full = crate::server::router(context.clone())
+            .await
+            .with_state(context.clone());
+        let api = crate::server::api::router(context.clone())
+            .await
+            .with_state(context);
+
+        assert_eq!(status(full).await, StatusCode::OK);
+        assert_eq!(status(api).await, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
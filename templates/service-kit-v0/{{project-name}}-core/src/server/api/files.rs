@@ -0,0 +1,672 @@
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Multipart, Path, Query, State,
+    },
+    handler::Handler,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::StreamReader;
+
+use crate::storage::{
+    StorageBatch, StorageEvent, StorageFileAttributes, StorageFileMeta, StoragePath,
+};
+
+/// How many entries a listing returns when the request doesn't say.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Metadata is sent and returned as headers with this prefix.
+const METADATA_PREFIX: &str = "x-meta-";
+
+/// Tags are sent and returned as a comma-separated list in this header.
+const TAGS_HEADER: &str = "x-tags";
+
+/// The largest batch request accepted, counting every part.
+const MAX_BATCH_SIZE: usize = 1024 * 1024 * 1024;
+
+pub async fn router(_context: crate::WebContext) -> Router<crate::WebContext> {
+    Router::new()
+        .route(
+            "/files",
+            get(list).post(batch.layer(DefaultBodyLimit::max(MAX_BATCH_SIZE))),
+        )
+        .route("/files/search", get(search))
+        .route("/files/watch", get(watch))
+        .route(
+            "/files/*path",
+            get(download).head(stat).put(upload).delete(remove),
+        )
+        .route("/versions/*path", get(versions).post(restore))
+        .route("/tags/:tag", get(tagged))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    /// The directory to list. Defaults to the root.
+    prefix: Option<String>,
+    /// List every file below the prefix, rather than its immediate children.
+    #[serde(default)]
+    recursive: bool,
+    /// The cursor returned with the previous page.
+    cursor: Option<String>,
+    /// The maximum number of entries to return.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// The words to look for. Files must contain all of them.
+    q: String,
+    /// The maximum number of files to return.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchQuery {
+    /// Only report changes at or below this path. Defaults to the root.
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    /// Link the path to already stored contents with this SHA-256 hash,
+    /// instead of reading the request body.
+    hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VersionQuery {
+    /// A previous version of the file, as numbered by `/versions`.
+    version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreQuery {
+    /// The previous version to make current again.
+    version: i64,
+}
+
+/// One step of a batch request. Inserted contents are sent as their own
+/// multipart part, named by `part`. A rename only replaces a file already at
+/// `to` when it sets `overwrite`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum BatchOperation {
+    Insert {
+        path: String,
+        part: String,
+    },
+    Remove {
+        path: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+        #[serde(default)]
+        overwrite: bool,
+    },
+}
+
+/// Wildcard captures don't carry the leading separator storage paths need.
+fn storage_path(path: &str) -> crate::Result<StoragePath> {
+    Ok(format!("/{}", path.trim_start_matches('/')).parse()?)
+}
+
+/// Attributes given with an upload: the content type, `x-meta-*` headers
+/// as metadata, and the tags listed in `x-tags`.
+fn request_attributes(headers: &HeaderMap) -> StorageFileAttributes {
+    let text = |value: &HeaderValue| value.to_str().ok().map(str::to_string);
+
+    StorageFileAttributes {
+        content_type: headers.get(header::CONTENT_TYPE).and_then(text),
+        metadata: headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = name.as_str().strip_prefix(METADATA_PREFIX)?;
+
+                Some((key.to_string(), text(value)?))
+            })
+            .collect(),
+        tags: headers
+            .get_all(TAGS_HEADER)
+            .iter()
+            .filter_map(text)
+            .flat_map(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    }
+}
+
+fn meta_headers(meta: &StorageFileMeta) -> HeaderMap {
+    let attributes = &meta.attributes;
+    let mut headers: HeaderMap = [
+        (
+            header::CONTENT_TYPE,
+            attributes.content_type_for(&meta.name),
+        ),
+        (header::CONTENT_LENGTH, meta.size.to_string()),
+        (header::ETAG, format!("\"{}\"", meta.hash)),
+        (
+            header::LAST_MODIFIED,
+            meta.updated_at
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, HeaderValue::from_str(&value).ok()?)))
+    .collect();
+
+    // metadata that can't be spelled as a header is left out
+    for (key, value) in &attributes.metadata {
+        let name = HeaderName::from_bytes(format!("{}{}", METADATA_PREFIX, key).as_bytes());
+
+        if let (Ok(name), Ok(value)) = (name, HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    }
+
+    if !attributes.tags.is_empty() {
+        let tags = attributes
+            .tags
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",");
+
+        if let Ok(tags) = HeaderValue::from_str(&tags) {
+            headers.insert(TAGS_HEADER, tags);
+        }
+    }
+
+    headers
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn list(
+    State(context): State<crate::WebContext>,
+    Query(query): Query<ListQuery>,
+) -> crate::Result<Response> {
+    let prefix = query.prefix.as_deref().unwrap_or("/").parse()?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    Ok(if query.recursive {
+        Json(
+            context
+                .storage
+                .walk_page(prefix, query.cursor, limit)
+                .await?,
+        )
+        .into_response()
+    } else {
+        Json(
+            context
+                .storage
+                .list_page(prefix, query.cursor, limit)
+                .await?,
+        )
+        .into_response()
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn download(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> crate::Result<Response> {
+    let path = storage_path(&path)?;
+    let file = match query.version {
+        Some(version) => context.storage.open_version(path, version).await?,
+        None => context.storage.open(path).await?,
+    };
+
+    Ok((meta_headers(&file.meta), Body::from_stream(file)).into_response())
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn stat(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+) -> crate::Result<Response> {
+    let meta = context.storage.stat(storage_path(&path)?).await?;
+
+    Ok(meta_headers(&meta).into_response())
+}
+
+/// Store the request body at `path`. A `Content-Type`, `x-meta-*` headers
+/// and `x-tags` replace the file's attributes; without any of them, an
+/// existing file keeps the attributes it had.
+#[tracing::instrument(level = "debug", skip(context, headers, body))]
+async fn upload(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> crate::Result<Response> {
+    let path = storage_path(&path)?;
+    let attributes = request_attributes(&headers);
+
+    let created = match query.hash {
+        Some(hash) => {
+            let created = context.storage.link(path.clone(), &hash).await?;
+
+            if !attributes.is_empty() {
+                context
+                    .storage
+                    .set_attributes(path.clone(), attributes)
+                    .await?;
+            }
+
+            created
+        }
+        None => {
+            let mut reader =
+                StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+
+            context
+                .storage
+                .put_with(path.clone(), &mut reader, attributes)
+                .await?
+        }
+    };
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(context.storage.stat(path).await?)).into_response())
+}
+
+/// Apply several changes at once, all or nothing. The request is a multipart
+/// form with an `operations` part holding a JSON list of `BatchOperation`s,
+/// plus one part for the contents of each insert.
+#[tracing::instrument(level = "debug", skip(context, multipart))]
+async fn batch(
+    State(context): State<crate::WebContext>,
+    mut multipart: Multipart,
+) -> crate::Result<StatusCode> {
+    let mut operations: Vec<BatchOperation> = vec![];
+    // parts are spooled to temporary files, removed when the request ends
+    let mut parts = HashMap::new();
+
+    while let Some(mut field) = multipart.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+
+        if name == "operations" {
+            operations = serde_json::from_slice(&field.bytes().await?)?;
+            continue;
+        }
+
+        let spooled = tempfile::NamedTempFile::new()?;
+        let mut file = tokio::fs::File::from_std(spooled.reopen()?);
+
+        while let Some(chunk) = field.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+        parts.insert(name, spooled);
+    }
+
+    let mut batch = StorageBatch::new();
+
+    for operation in operations {
+        batch = match operation {
+            BatchOperation::Insert { path, part } => {
+                let spooled = parts
+                    .get(&part)
+                    .ok_or(crate::Error::MissingBatchPart(part))?;
+
+                batch.insert_file(storage_path(&path)?, spooled.path().to_path_buf())
+            }
+            BatchOperation::Remove { path } => batch.remove(storage_path(&path)?),
+            BatchOperation::Rename {
+                from,
+                to,
+                overwrite,
+            } => batch.rename(storage_path(&from)?, storage_path(&to)?, overwrite),
+        };
+    }
+
+    context.storage.apply_batch(batch).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn remove(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+) -> crate::Result<StatusCode> {
+    context.storage.delete(storage_path(&path)?).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn versions(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+) -> crate::Result<Response> {
+    Ok(Json(context.storage.versions(storage_path(&path)?).await?).into_response())
+}
+
+/// Make a previous version current again. The contents it replaces become
+/// the newest version, so the restore can be undone the same way.
+#[tracing::instrument(level = "debug", skip(context))]
+async fn restore(
+    State(context): State<crate::WebContext>,
+    Path(path): Path<String>,
+    Query(query): Query<RestoreQuery>,
+) -> crate::Result<Response> {
+    let path = storage_path(&path)?;
+
+    context.storage.restore(path.clone(), query.version).await?;
+
+    Ok(Json(context.storage.stat(path).await?).into_response())
+}
+
+#[tracing::instrument(level = "debug", skip(context))]
+async fn tagged(
+    State(context): State<crate::WebContext>,
+    Path(tag): Path<String>,
+) -> crate::Result<Response> {
+    Ok(Json(context.storage.find_by_tag(&tag).await?).into_response())
+}
+
+/// Search the contents of stored text files. This route takes precedence
+/// over the file stored at `/search`, if there is one.
+#[tracing::instrument(level = "debug", skip(context))]
+async fn search(
+    State(context): State<crate::WebContext>,
+    Query(query): Query<SearchQuery>,
+) -> crate::Result<Response> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    Ok(Json(context.storage.search(&query.q, limit).await?).into_response())
+}
+
+/// Report changes to stored files as they're made: as JSON text messages
+/// over a WebSocket when the request asks to upgrade, and as server-sent
+/// events otherwise. Either ends if the watcher falls too far behind, after
+/// which it should reload whatever it shows before watching again, and when
+/// the server shuts down. Like `/files/search`, this takes precedence over a
+/// file stored at `/watch`.
+#[tracing::instrument(level = "debug", skip(context, upgrade))]
+async fn watch(
+    State(context): State<crate::WebContext>,
+    Query(query): Query<WatchQuery>,
+    upgrade: Option<WebSocketUpgrade>,
+) -> crate::Result<Response> {
+    let prefix = query.prefix.as_deref().unwrap_or("/").parse()?;
+    // watchers would otherwise hold the server open until the drain timeout
+    let events = context
+        .storage
+        .watch(prefix)?
+        .take_until(context.shutdown.wait())
+        .boxed();
+
+    Ok(match upgrade {
+        Some(upgrade) => upgrade
+            .on_upgrade(|socket| forward_events(socket, events))
+            .into_response(),
+        None => Sse::new(events.map(|event| Event::default().json_data(event)))
+            .keep_alive(KeepAlive::default())
+            .into_response(),
+    })
+}
+
+/// Send each event over `socket` until the events end or the client leaves.
+/// Anything the client sends is ignored.
+async fn forward_events(mut socket: WebSocket, mut events: BoxStream<'static, StorageEvent>) {
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+
+                if socket.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => {
+                if !matches!(message, Some(Ok(_))) {
+                    return;
+                }
+            }
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        Router,
+    };
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    /// The API router over a context built from `config`.
+    async fn app(config: serde_json::Value) -> Result<Router, Box<dyn std::error::Error>> {
+        let settings = crate::settings::Settings {
+            cli: clap::Parser::parse_from(["test", "-a", "test", "debug"]),
+            config: serde_json::from_value(config)?,
+        };
+        let network = crate::settings::NetworkSettings {
+            host: "localhost".to_string(),
+            port: 0,
+        };
+        let context = crate::WebContext::new(network, settings).await?;
+
+        Ok(super::super::router(context.clone())
+            .await
+            .with_state(context))
+    }
+
+    /// The status `request` gets, once its body has been read to the end
+    /// the way a client would, so nothing it holds open outlasts it.
+    async fn send(app: &Router, request: Request<Body>) -> StatusCode {
+        let response = app
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("Router never fails");
+        let status = response.status();
+
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Failed to read response body");
+
+        status
+    }
+
+    fn put(path: &str, contents: &'static str) -> Request<Body> {
+        Request::put(path)
+            .body(Body::from(contents))
+            .expect("Failed to build request")
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path)
+            .body(Body::empty())
+            .expect("Failed to build request")
+    }
+
+    /// A multipart batch request carrying `operations` and one part per
+    /// `(name, contents)`.
+    fn batch(operations: serde_json::Value, parts: &[(&str, &str)]) -> Request<Body> {
+        let mut body = format!(
+            "--limit\r\nContent-Disposition: form-data; name=\"operations\"\r\n\r\n{}\r\n",
+            operations
+        );
+
+        for (name, contents) in parts {
+            body.push_str(&format!(
+                "--limit\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n{}\r\n",
+                name, name, contents
+            ));
+        }
+
+        body.push_str("--limit--\r\n");
+
+        Request::post("/files")
+            .header("content-type", "multipart/form-data; boundary=limit")
+            .body(Body::from(body))
+            .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn routes() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let app = app(serde_json::json!({
+            "storage": {
+                "path": temp_dir.path().join("files.db"),
+                "limits": { "max_file_size": 8, "max_total_size": 15 },
+            },
+        }))
+        .await?;
+
+        assert_eq!(
+            send(&app, put("/files/a.txt", "hello")).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            send(&app, put("/files/a.txt", "hallo")).await,
+            StatusCode::OK
+        );
+        assert_eq!(send(&app, get("/files/a.txt")).await, StatusCode::OK);
+        assert_eq!(
+            send(&app, get("/files/missing")).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&app, put("/files/big.txt", "too large")).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            send(&app, put("/files/b.txt", "12345678")).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            send(&app, put("/files/c.txt", "123")).await,
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+
+        let remove = Request::delete("/files/b.txt").body(Body::empty())?;
+
+        assert_eq!(send(&app, remove).await, StatusCode::NO_CONTENT);
+
+        // batches are applied whole, or not at all
+        let operations = serde_json::json!([
+            { "op": "insert", "path": "/c.txt", "part": "c" },
+            { "op": "rename", "from": "/a.txt", "to": "/d.txt" },
+        ]);
+
+        assert_eq!(
+            send(&app, batch(operations.clone(), &[])).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(send(&app, get("/files/a.txt")).await, StatusCode::OK);
+        assert_eq!(
+            send(&app, batch(operations, &[("c", "abc")])).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(send(&app, get("/files/c.txt")).await, StatusCode::OK);
+        assert_eq!(send(&app, get("/files/a.txt")).await, StatusCode::NOT_FOUND);
+        assert_eq!(send(&app, get("/files/d.txt")).await, StatusCode::OK);
+
+        // a rename only replaces a file when asked to
+        let rename = |overwrite| {
+            serde_json::json!([
+                { "op": "rename", "from": "/c.txt", "to": "/d.txt", "overwrite": overwrite },
+            ])
+        };
+
+        assert_eq!(
+            send(&app, batch(rename(false), &[])).await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(send(&app, get("/files/c.txt")).await, StatusCode::OK);
+        assert_eq!(
+            send(&app, batch(rename(true), &[])).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(send(&app, get("/files/c.txt")).await, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unsupported() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let app = app(serde_json::json!({
+            "storage": { "backend": "memory" },
+            "database": { "path": temp_dir.path().join("state.db") },
+        }))
+        .await?;
+
+        assert_eq!(
+            send(&app, put("/files/a.txt", "hello")).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            send(&app, get("/files/search?q=hello")).await,
+            StatusCode::NOT_IMPLEMENTED
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filesystem() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let app = app(serde_json::json!({
+            "storage": { "backend": "filesystem", "path": temp_dir.path().join("files") },
+            "database": { "path": temp_dir.path().join("state.db") },
+        }))
+        .await?;
+
+        assert_eq!(
+            send(&app, put("/files/a.txt", "hello")).await,
+            StatusCode::CREATED
+        );
+
+        // there's nowhere to keep attributes, so nothing is written
+        let tagged = Request::put("/files/b.txt")
+            .header("x-tags", "draft")
+            .body(Body::from("hello"))?;
+
+        assert_eq!(send(&app, tagged).await, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(send(&app, get("/files/b.txt")).await, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
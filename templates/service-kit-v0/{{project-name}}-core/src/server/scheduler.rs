@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use crate::scheduler::ScheduledJob;
+
+/// Every job the full server runs on a schedule. Add a service's periodic
+/// jobs here; their status shows at `/admin/jobs`.
+fn jobs(_context: &crate::WebContext) -> Vec<Arc<dyn ScheduledJob>> {
+    vec![]
+}
+
+/// Register the jobs and start running them as one of the context's
+/// background tasks, which ends once shutdown begins and no job is running.
+pub async fn start(context: &crate::WebContext) -> crate::Result<()> {
+    let mut scheduler = context.scheduler.clone();
+
+    for job in jobs(context) {
+        scheduler.register(job)?;
+    }
+
+    scheduler.prepare().await?;
+
+    context.spawn(scheduler.run(context.shutdown.clone()));
+
+    Ok(())
+}
@@ -1,4 +1,11 @@
-use axum::{extract::State, http::Uri, response::IntoResponse, routing::get, Router};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Uri},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
 use rust_embed::RustEmbed;
 
 pub async fn router(_context: crate::WebContext) -> Router<crate::WebContext> {
@@ -19,22 +26,21 @@ async fn embed_handler(
     State(app_context): State<crate::WebContext>,
     uri: Uri,
 ) -> impl IntoResponse {
-    let mut path = uri.path().trim_start_matches('/').to_string();
+    let Ok(storage_path) = uri.path().parse::<crate::storage::StoragePath>() else {
+        tracing::debug!("invalid asset path: {}", uri);
+        return (axum::http::StatusCode::BAD_REQUEST, "400 Bad Request").into_response();
+    };
+    let mut path = storage_path.to_string().trim_start_matches('/').to_string();
 
     if path.starts_with("dist/") {
         path = path.replace("dist/", "");
     }
 
-    match crate::storage::StorageFile::get(&app_context, &uri.to_string()).await {
+    match crate::storage::StorageFile::stream(&app_context, &storage_path.to_string()).await {
         Ok(Some(file)) => {
-            tracing::debug!("found storage file: {}", uri);
-            let mime = mime_guess::from_path(uri.to_string()).first_or_octet_stream();
-
-            (
-                [(axum::http::header::CONTENT_TYPE, mime.as_ref())],
-                file.contents,
-            )
-                .into_response()
+            tracing::debug!("found storage file: {}", storage_path);
+
+            (storage_headers(&file.meta), Body::from_stream(file)).into_response()
         }
         _ => {
             tracing::debug!("no storage file found: {}", path);
@@ -43,6 +49,36 @@ async fn embed_handler(
     }
 }
 
+/// Headers for serving a stored file: its stored content type, or one guessed
+/// from its name, and any caching headers kept in its metadata.
+fn storage_headers(meta: &crate::storage::StorageFileMeta) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let content_type = meta.attributes.content_type_for(&meta.name);
+
+    if let Ok(content_type) = HeaderValue::from_str(&content_type) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(meta.size));
+
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", meta.hash)) {
+        headers.insert(header::ETAG, etag);
+    }
+
+    for name in CACHE_HEADERS {
+        let value = meta.attributes.metadata.get(name.as_str());
+
+        if let Some(Ok(value)) = value.map(|value| HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    }
+
+    headers
+}
+
+/// Metadata keys that are passed on as headers when a stored file is served.
+const CACHE_HEADERS: [HeaderName; 2] = [header::CACHE_CONTROL, header::EXPIRES];
+
 #[derive(RustEmbed)]
 #[folder = "dist/"]
 struct Asset;
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use crate::queue::Worker;
+
+/// Every worker `server worker` runs, each on its own queue. Add a service's
+/// workers here; enqueue their jobs through `WebContext::queue`.
+fn workers(_context: &crate::WebContext) -> Vec<Arc<dyn Worker>> {
+    vec![]
+}
+
+pub async fn init(context: crate::WebContext) -> crate::Result<()> {
+    let workers = workers(&context);
+
+    if workers.is_empty() {
+        tracing::warn!("No queue workers are registered, nothing to run");
+        context.shutdown.trigger();
+    } else {
+        tracing::info!(
+            "Running workers for {}",
+            workers
+                .iter()
+                .map(|worker| worker.queue())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    for worker in workers {
+        let queue = context.queue.clone();
+        let shutdown = context.shutdown.clone();
+
+        context.spawn(async move { queue.work(worker, &shutdown).await });
+    }
+
+    context.drain_tasks().await;
+    context.close().await;
+
+    Ok(())
+}
@@ -2,27 +2,35 @@ mod cli;
 mod client;
 mod configuration;
 mod environment;
+mod files;
+mod migrations;
 mod network_settings;
 mod server;
 mod service;
 mod service_settings;
+mod storage;
 
 use clap::Parser;
 use config::Config;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use client::ClientResource;
-use configuration::Configuration;
+use configuration::{Configuration, StorageBackendKind};
+use migrations::MigrationDatabase;
 use server::ServerMode;
 use service::ServiceOperation;
 
 pub use cli::{Cli, Command};
 pub use client::Client;
 pub use environment::Environment;
+pub use files::Files;
+pub use migrations::Migrations;
 pub use network_settings::NetworkSettings;
 pub use server::Server;
 pub use service::Service;
 pub use service_settings::ServiceSettings;
+pub use storage::Storage;
 
 #[derive(Clone, Debug)]
 pub struct Settings {
@@ -60,6 +68,32 @@ impl Settings {
 
                 println!("{:#?}", self);
             }
+            Command::Migrations(migration_details) => {
+                tracing::info!("Migrations command: {:?}", migration_details);
+
+                let path = match migration_details.db {
+                    MigrationDatabase::Storage => match self.storage_backend() {
+                        StorageBackendKind::Sqlite => self.storage_path(),
+                        _ => Err(crate::storage::StorageError::MaintenanceUnsupported)?,
+                    },
+                    MigrationDatabase::State => self.database_path(),
+                };
+                let collection = crate::storage::StorageCollection::connect(path).await?;
+
+                migration_details
+                    .operation
+                    .unwrap_or_default()
+                    .exec(collection, migration_details.db)
+                    .await?;
+            }
+            Command::Storage(storage_details) => {
+                tracing::info!("Storage command: {:?}", storage_details);
+
+                storage_details
+                    .operation
+                    .exec(self.storage().await?)
+                    .await?;
+            }
             Command::Tui => {
                 tracing::info!("Starting TUI");
 
@@ -71,6 +105,20 @@ impl Settings {
                 let context =
                     crate::context::WebContext::new(server_details.settings, self.clone()).await?;
 
+                self.schedule_backups(&context);
+                self.schedule_cache_sweeps(&context);
+
+                tokio::spawn(context.shutdown.clone().trigger_on_signal());
+
+                if server_details.seed {
+                    let seed = self
+                        .storage_seed()
+                        .ok_or(crate::Error::SeedNotConfiguredError)?;
+                    let seeded = context.seed(&seed).await?;
+
+                    tracing::info!("Seeded {} storage files", seeded.len());
+                }
+
                 match server_details.mode {
                     Some(mode) => mode.exec(context).await?,
                     None => {
@@ -94,7 +142,10 @@ impl Settings {
                     }
                 };
 
-                tracing::info!("{}", response);
+                // results go to stdout, so they can be piped; logs don't
+                if !response.is_null() {
+                    println!("{:#}", response);
+                }
             }
             Command::Service(service_details) => {
                 tracing::info!("Service command: {:?}", service_details);
@@ -119,40 +170,313 @@ impl Settings {
         Ok(())
     }
 
+    /// The configured storage seed, if there is one.
+    pub fn storage_seed(&self) -> Option<crate::storage::StorageSeed> {
+        self.config
+            .storage
+            .as_ref()
+            .and_then(|storage| storage.seed.clone())
+    }
+
+    fn storage_backend(&self) -> StorageBackendKind {
+        self.config
+            .storage
+            .as_ref()
+            .map(|storage| storage.backend)
+            .unwrap_or_default()
+    }
+
+    /// Open whichever storage backend the configuration selects.
+    pub async fn storage(&self) -> crate::Result<crate::storage::Storage> {
+        let backend = self.storage_backend();
+
+        tracing::info!("Using {:?} storage backend", backend);
+
+        if !self.storage_limits().is_empty() && !matches!(backend, StorageBackendKind::Sqlite) {
+            tracing::warn!("Storage limits are only enforced by the sqlite backend");
+        }
+
+        Ok(match backend {
+            StorageBackendKind::Sqlite => Arc::new(self.storage_collection().await?),
+            StorageBackendKind::Filesystem => {
+                Arc::new(crate::storage::FilesystemStorage::new(self.storage_dir())?)
+            }
+            StorageBackendKind::Memory => Arc::new(crate::storage::MemoryStorage::default()),
+        })
+    }
+
+    /// The SQLite collection at the configured storage path, migrated and
+    /// with the configured history and limits, for the sqlite backend.
+    pub async fn storage_collection(&self) -> crate::Result<crate::storage::StorageCollection> {
+        let history = self
+            .config
+            .storage
+            .as_ref()
+            .map(|storage| storage.history)
+            .unwrap_or_default();
+
+        Ok(
+            crate::storage::StorageCollection::file_index(self.storage_path())
+                .await?
+                .with_history(history)
+                .with_limits(self.storage_limits()),
+        )
+    }
+
+    /// Open the database the cache, queue and scheduler keep their state in,
+    /// migrated. With the sqlite backend and no other `[database] path`,
+    /// that's the storage database, sharing `storage`'s connections.
+    pub async fn database(
+        &self,
+        storage: &crate::storage::Storage,
+    ) -> crate::Result<crate::storage::StorageCollection> {
+        let configured = self
+            .config
+            .database
+            .as_ref()
+            .and_then(|database| database.path.clone());
+
+        match storage.as_collection() {
+            Some(collection)
+                if configured
+                    .is_none_or(|path| path == self.configured_storage_path("storage.db")) =>
+            {
+                collection.migrate_state().await?;
+
+                Ok(collection.clone())
+            }
+            _ => crate::storage::StorageCollection::state_index(self.database_path()).await,
+        }
+    }
+
+    /// Start taking the configured backups of the context's storage in the
+    /// background, if any are.
+    fn schedule_backups(&self, context: &crate::context::WebContext) {
+        let Some(schedule) = self
+            .config
+            .storage
+            .as_ref()
+            .and_then(|storage| storage.backups.clone())
+        else {
+            return;
+        };
+        let Some(collection) = context.storage.as_collection().cloned() else {
+            tracing::warn!("Scheduled backups only cover the sqlite backend, skipping");
+
+            return;
+        };
+        let source = self.storage_path();
+
+        tracing::info!(
+            "Backing up storage to {} every {} minutes",
+            schedule.dir.display(),
+            schedule.every_minutes
+        );
+
+        let shutdown = context.shutdown.clone();
+
+        context.spawn(async move {
+            collection
+                .backup_on_schedule(schedule, source, &shutdown)
+                .await
+        });
+    }
+
+    /// The key-value cache, kept in `database`.
+    pub fn cache(&self, database: &crate::storage::StorageCollection) -> crate::cache::Cache {
+        crate::cache::Cache::new(database, &self.cache_options())
+    }
+
+    /// Start deleting expired cache entries in the background.
+    fn schedule_cache_sweeps(&self, context: &crate::context::WebContext) {
+        let cache = context.cache.clone();
+        let shutdown = context.shutdown.clone();
+        let interval = self.cache_options().sweep_interval();
+
+        context.spawn(async move { cache.sweep_on_schedule(interval, &shutdown).await });
+    }
+
+    fn cache_options(&self) -> crate::cache::CacheOptions {
+        self.config.cache.clone().unwrap_or_default()
+    }
+
+    /// The job queue, kept in `database`.
+    pub fn queue(&self, database: &crate::storage::StorageCollection) -> crate::queue::Queue {
+        crate::queue::Queue::new(database, &self.config.queue.clone().unwrap_or_default())
+    }
+
+    /// The job scheduler, keeping its locks and history in `database`. It
+    /// has no jobs until the server registers them.
+    pub fn scheduler(
+        &self,
+        database: &crate::storage::StorageCollection,
+    ) -> crate::scheduler::Scheduler {
+        crate::scheduler::Scheduler::new(
+            database,
+            &self.config.scheduler.clone().unwrap_or_default(),
+        )
+    }
+
+    /// What tells the server and its background tasks to stop.
+    pub fn shutdown(&self) -> crate::shutdown::Shutdown {
+        crate::shutdown::Shutdown::new(&self.config.shutdown.clone().unwrap_or_default())
+    }
+
+    fn storage_limits(&self) -> crate::storage::StorageLimits {
+        self.config
+            .storage
+            .as_ref()
+            .map(|storage| storage.limits.clone())
+            .unwrap_or_default()
+    }
+
+    /// `name` in the user's config directory.
+    fn default_path(&self, name: &str) -> PathBuf {
+        // use directories to get a default data directory in user's config path
+        match dirs::config_local_dir() {
+            Some(mut path) => {
+                path.push(self.cli.global.app_name.to_lowercase());
+                path.push(name);
+                path
+            }
+            None => {
+                // otherwise we start in a temp directory
+                std::env::temp_dir().join(name)
+            }
+        }
+    }
+
+    /// The configured storage path, or `default_name` in the user's config directory.
+    fn configured_storage_path(&self, default_name: &str) -> PathBuf {
+        self.config
+            .storage
+            .as_ref()
+            .and_then(|storage| storage.path.clone())
+            .unwrap_or_else(|| self.default_path(default_name))
+    }
+
+    /// The root directory of the filesystem storage backend.
+    pub fn storage_dir(&self) -> PathBuf {
+        let path = self.configured_storage_path("files");
+
+        tracing::info!("Using storage directory: {}", path.display());
+
+        path
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     pub fn storage_path(&self) -> PathBuf {
         tracing::info!("Getting storage path");
-        let path = match &self.config.storage {
-            Some(storage) => storage.path.clone(),
-            None => {
-                // use directories to get a default data directory in user's config path
-                match dirs::config_local_dir() {
-                    Some(mut path) => {
-                        path.push(self.cli.global.app_name.to_lowercase());
-                        path.push("storage.db");
-                        path
-                    }
-                    None => {
-                        // otherwise we start in a temp directory
-                        std::env::temp_dir().join("storage.db")
-                    }
-                }
-            }
-        };
+        let path = self.configured_storage_path("storage.db");
 
         tracing::info!("Using storage path: {}", path.display());
 
-        // ensure the file and path exist
-        if let Some(parent) = path.parent() {
-            tracing::info!("Ensuring storage path exists: {:?}", parent);
-            std::fs::create_dir_all(parent).expect("Unable to create storage directory");
-        }
+        ensure_database_file(&path);
 
-        if std::fs::metadata(&path).is_err() {
-            tracing::info!("Ensuring storage file exists: {:?}", path);
-            std::fs::File::create(&path).expect("Unable to create storage file");
-        }
+        path
+    }
+
+    /// The database file the cache, queue and scheduler keep their state in:
+    /// the configured `[database] path`, or else the storage database with
+    /// the sqlite backend and `state.db` in the user's config directory with
+    /// the others.
+    pub fn database_path(&self) -> PathBuf {
+        let configured = self
+            .config
+            .database
+            .as_ref()
+            .and_then(|database| database.path.clone());
+        let path = match configured {
+            Some(path) => path,
+            None if matches!(self.storage_backend(), StorageBackendKind::Sqlite) => {
+                return self.storage_path()
+            }
+            None => self.default_path("state.db"),
+        };
+
+        tracing::info!("Using state database path: {}", path.display());
+
+        ensure_database_file(&path);
 
         path
     }
 }
+
+/// Create the database file at `path`, and the directories above it, if
+/// they don't exist yet.
+fn ensure_database_file(path: &std::path::Path) {
+    if let Some(parent) = path.parent() {
+        tracing::info!("Ensuring database directory exists: {:?}", parent);
+        std::fs::create_dir_all(parent).expect("Unable to create database directory");
+    }
+
+    if std::fs::metadata(path).is_err() {
+        tracing::info!("Ensuring database file exists: {:?}", path);
+        std::fs::File::create(path).expect("Unable to create database file");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use tempfile::tempdir;
+
+    /// Settings as if the program ran with `args` and `config`.
+    fn settings(
+        args: &[&str],
+        config: serde_json::Value,
+    ) -> Result<super::Settings, Box<dyn std::error::Error>> {
+        Ok(super::Settings {
+            cli: clap::Parser::try_parse_from([&["test", "-a", "test"], args].concat())?,
+            config: serde_json::from_value(config)?,
+        })
+    }
+
+    /// The names of the tables in the database at `path`.
+    async fn tables(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", path.display())).await?;
+        let tables: Vec<(String,)> =
+            sqlx::query_as("select name from sqlite_master where type = 'table'")
+                .fetch_all(&pool)
+                .await?;
+
+        pool.close().await;
+
+        Ok(tables.into_iter().map(|(name,)| name).collect())
+    }
+
+    #[tokio::test]
+    async fn migrations() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let storage_db = temp_dir.path().join("storage.db");
+        let state_db = temp_dir.path().join("state.db");
+        let config = serde_json::json!({
+            "storage": { "path": storage_db },
+            "database": { "path": state_db },
+        });
+
+        settings(&["migrations", "--db", "state", "up"], config.clone())?
+            .exec()
+            .await?;
+
+        let state = tables(&state_db).await?;
+
+        assert!(state.contains(&"cache_entries".to_string()));
+        assert!(state.contains(&"queue_jobs".to_string()));
+        assert!(!state.contains(&"files".to_string()));
+        assert!(!storage_db.exists());
+
+        settings(&["migrations", "--db", "storage", "up"], config)?
+            .exec()
+            .await?;
+
+        let storage = tables(&storage_db).await?;
+
+        assert!(storage.contains(&"files".to_string()));
+        assert!(!storage.contains(&"cache_entries".to_string()));
+        assert!(!tables(&state_db).await?.contains(&"files".to_string()));
+
+        Ok(())
+    }
+}
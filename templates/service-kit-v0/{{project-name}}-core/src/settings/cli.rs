@@ -1,7 +1,7 @@
 use clap::Parser;
 use std::path::PathBuf;
 
-use super::{Client, Environment, Server, Service};
+use super::{Client, Environment, Migrations, Server, Service, Storage};
 
 /// A CLI application that helps do non-standard AzerothCore db tasks
 #[derive(Clone, Debug, Parser)]
@@ -81,8 +81,10 @@ pub struct GlobalOpts {
 #[clap(rename_all = "kebab-case")]
 pub enum Command {
     Debug,
+    Migrations(Migrations),
     Tui,
     Server(Server),
     Client(Client),
     Service(Service),
+    Storage(Storage),
 }
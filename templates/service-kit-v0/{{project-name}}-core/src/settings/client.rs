@@ -3,7 +3,7 @@ use serde_json::Value;
 use std::str::FromStr;
 use strum::{EnumString, VariantNames};
 
-use super::NetworkSettings;
+use super::{Files, NetworkSettings};
 
 #[derive(Clone, Debug, Parser)]
 #[clap(rename_all = "kebab-case")]
@@ -22,6 +22,8 @@ pub struct Client {
 pub enum ClientResource {
     /// The health check api.
     Health,
+    /// The file storage api.
+    Files(Files),
 }
 
 impl ClientResource {
@@ -30,9 +32,17 @@ impl ClientResource {
     }
 
     pub async fn exec(&self, config: NetworkSettings) -> crate::Result<Value> {
-        Ok(serde_json::to_value(&match self {
-            ClientResource::Health => crate::client::health(config).await?,
-        })?)
+        Ok(match self {
+            ClientResource::Health => serde_json::to_value(crate::client::health(config).await?)?,
+            ClientResource::Files(files) => {
+                files
+                    .operation
+                    .clone()
+                    .unwrap_or_default()
+                    .exec(&crate::WebClient::with_settings(config))
+                    .await?
+            }
+        })
     }
 
     pub fn select() -> crate::Result<Self> {
@@ -5,11 +5,55 @@ use std::path::PathBuf;
 pub struct Configuration {
     pub db: Option<Database>,
     pub storage: Option<Storage>,
+    /// Where the cache, queue and scheduler keep their state.
+    pub database: Option<StateDatabase>,
+    /// The key-value cache kept in the state database.
+    pub cache: Option<crate::cache::CacheOptions>,
+    /// The job queue kept in the state database.
+    pub queue: Option<crate::queue::QueueOptions>,
+    /// The periodic jobs the full server runs.
+    pub scheduler: Option<crate::scheduler::SchedulerOptions>,
+    /// How the server stops.
+    pub shutdown: Option<crate::shutdown::ShutdownOptions>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Storage {
-    pub path: PathBuf,
+    /// Which backend keeps stored files.
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    /// The database file for the SQLite backend, or the root directory for
+    /// the filesystem backend. Defaults to the user's config directory.
+    pub path: Option<PathBuf>,
+    /// What `server --seed` puts into the collection.
+    pub seed: Option<crate::storage::StorageSeed>,
+    /// How many previous versions of each file the SQLite backend keeps.
+    /// With the default of 0, overwritten and removed contents are dropped.
+    #[serde(default)]
+    pub history: usize,
+    /// How much the SQLite backend accepts. Unlimited by default.
+    #[serde(default)]
+    pub limits: crate::storage::StorageLimits,
+    /// Regular backups of the SQLite backend while the server runs.
+    pub backups: Option<crate::storage::StorageBackupSchedule>,
+}
+
+/// The SQLite database the cache, queue and scheduler keep their state in.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StateDatabase {
+    /// The database file. Without one, the sqlite storage backend's database
+    /// is shared, and other backends use `state.db` in the user's config
+    /// directory.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageBackendKind {
+    #[default]
+    Sqlite,
+    Filesystem,
+    Memory,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -0,0 +1,243 @@
+use clap::Parser;
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::Value;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Default, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub struct Files {
+    /// What to do with the stored files. Lists the root if not set.
+    #[clap(subcommand)]
+    pub operation: Option<FileOperation>,
+}
+
+#[derive(Clone, Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub enum FileOperation {
+    /// List stored files.
+    Ls {
+        /// The directory to list.
+        #[clap(default_value = "/")]
+        prefix: String,
+        /// List every file below the directory, not just its children.
+        #[clap(short, long)]
+        recursive: bool,
+    },
+    /// Download a stored file.
+    Get {
+        /// The stored path to download.
+        path: String,
+        /// Where to write the file. Defaults to its name in the current directory.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Download this previous version instead of the current contents.
+        #[clap(long)]
+        version: Option<i64>,
+    },
+    /// Upload a local file.
+    Put {
+        /// The local file to upload.
+        local: PathBuf,
+        /// The stored path to upload it to.
+        remote: String,
+        /// The content type to serve the file as, instead of guessing from its name.
+        #[clap(long)]
+        content_type: Option<String>,
+        /// Metadata to keep with the file, as `key=value`. Can be repeated.
+        #[clap(long = "meta", value_parser = parse_metadata)]
+        metadata: Vec<(String, String)>,
+        /// A tag to give the file. Can be repeated.
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Search the contents of stored text files.
+    Search {
+        /// The words to look for. Files must contain all of them.
+        query: String,
+        /// The maximum number of files to show.
+        #[clap(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print changes to stored files as they're made, until interrupted.
+    Watch {
+        /// Only show changes at or below this directory.
+        #[clap(default_value = "/")]
+        prefix: String,
+    },
+    /// List the stored files carrying a tag.
+    Tagged {
+        /// The tag to look for.
+        tag: String,
+    },
+    /// Delete a stored file.
+    Rm {
+        /// The stored path to delete.
+        path: String,
+    },
+    /// List the kept versions of a stored file, newest first.
+    Versions {
+        /// The stored path to list versions of.
+        path: String,
+    },
+    /// Make a previous version of a stored file current again.
+    Restore {
+        /// The stored path to restore.
+        path: String,
+        /// The version to restore, as listed by `versions`.
+        version: i64,
+    },
+    /// Upload a local directory, sending only new and changed files.
+    Sync {
+        /// The local directory to upload.
+        local: PathBuf,
+        /// The stored directory to upload it to.
+        remote: String,
+        /// Delete stored files that don't exist locally.
+        #[clap(long)]
+        delete: bool,
+        /// Compare every file by hash instead of trusting size and modification time.
+        #[clap(long)]
+        checksum: bool,
+        /// Print what would change without changing anything.
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+impl Default for FileOperation {
+    fn default() -> Self {
+        FileOperation::Ls {
+            prefix: "/".to_string(),
+            recursive: false,
+        }
+    }
+}
+
+fn parse_metadata(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) => Ok((key.to_lowercase(), value.to_string())),
+        None => Err(format!("expected key=value, got `{}`", pair)),
+    }
+}
+
+fn transfer_progress() -> ProgressBar {
+    ProgressBar::new(0).with_style(
+        ProgressStyle::with_template(
+            "{spinner} [{bar:40}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+        )
+        .expect("Invalid progress bar template")
+        .progress_chars("=> "),
+    )
+}
+
+fn sync_progress() -> ProgressBar {
+    ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("{spinner} [{bar:40}] {pos}/{len} {wide_msg}")
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    )
+}
+
+impl FileOperation {
+    pub async fn exec(&self, client: &crate::WebClient) -> crate::Result<Value> {
+        Ok(match self {
+            FileOperation::Ls {
+                prefix,
+                recursive: false,
+            } => serde_json::to_value(client.list_files(prefix).await?)?,
+            FileOperation::Ls {
+                prefix,
+                recursive: true,
+            } => serde_json::to_value(client.walk_files(prefix).await?)?,
+            FileOperation::Get {
+                path,
+                output,
+                version,
+            } => {
+                let output = match output {
+                    Some(output) => output.clone(),
+                    None => path
+                        .parse::<crate::storage::StoragePath>()?
+                        .file_name()?
+                        .into(),
+                };
+                client
+                    .download_file(path, *version, &output, &transfer_progress())
+                    .await?;
+
+                serde_json::json!({ "downloaded": path, "output": output })
+            }
+            FileOperation::Put {
+                local,
+                remote,
+                content_type,
+                metadata,
+                tags,
+            } => {
+                let attributes = crate::storage::StorageFileAttributes {
+                    content_type: content_type.clone(),
+                    metadata: metadata.iter().cloned().collect(),
+                    tags: tags.iter().cloned().collect(),
+                };
+
+                serde_json::to_value(
+                    client
+                        .upload_file(local, remote, &attributes, &transfer_progress())
+                        .await?,
+                )?
+            }
+            FileOperation::Search { query, limit } => {
+                let hits = client.search_files(query, *limit).await?;
+
+                for hit in &hits {
+                    println!("{}: {}", hit.file.full_path(), hit.snippet);
+                }
+
+                // already printed, one line per hit
+                Value::Null
+            }
+            FileOperation::Watch { prefix } => {
+                let events = client.watch_files(prefix).await?;
+                let mut events = std::pin::pin!(events);
+
+                while let Some(event) = events.next().await {
+                    println!("{}", event?);
+                }
+
+                serde_json::json!({ "watched": prefix })
+            }
+            FileOperation::Tagged { tag } => serde_json::to_value(client.tagged_files(tag).await?)?,
+            FileOperation::Rm { path } => {
+                client.remove_file(path).await?;
+
+                serde_json::json!({ "removed": path })
+            }
+            FileOperation::Versions { path } => {
+                serde_json::to_value(client.file_versions(path).await?)?
+            }
+            FileOperation::Restore { path, version } => {
+                serde_json::to_value(client.restore_file(path, *version).await?)?
+            }
+            FileOperation::Sync {
+                local,
+                remote,
+                delete,
+                checksum,
+                dry_run,
+            } => {
+                let options = crate::storage::StorageSyncOptions {
+                    delete: *delete,
+                    checksum: *checksum,
+                };
+                let plan = client.sync_plan(local, remote, &options).await?;
+
+                if !*dry_run {
+                    client.apply_sync(&plan, &sync_progress()).await?;
+                }
+
+                serde_json::json!({ "dry_run": dry_run, "plan": plan })
+            }
+        })
+    }
+}
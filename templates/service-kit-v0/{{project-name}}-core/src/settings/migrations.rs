@@ -0,0 +1,64 @@
+use clap::Parser;
+
+#[derive(Clone, Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub struct Migrations {
+    /// Which database's migrations to work with.
+    #[clap(long, value_enum, default_value_t)]
+    pub db: MigrationDatabase,
+    /// What to do with the migrations. Lists them if not set.
+    #[clap(subcommand)]
+    pub operation: Option<MigrationOperation>,
+}
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum MigrationDatabase {
+    /// The sqlite storage backend's database of files.
+    #[default]
+    Storage,
+    /// The database the cache, queue and scheduler keep their state in.
+    State,
+}
+
+#[derive(Clone, Debug, Default, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub enum MigrationOperation {
+    /// List applied and pending migrations.
+    #[default]
+    List,
+    /// Apply every pending migration.
+    Up,
+    /// Revert applied migrations until the target version is the latest.
+    Down {
+        /// The migration version to revert to. Use 0 to revert everything.
+        target: i64,
+    },
+}
+
+impl MigrationOperation {
+    pub async fn exec(
+        &self,
+        collection: crate::storage::StorageCollection,
+        db: MigrationDatabase,
+    ) -> crate::Result<()> {
+        let set = match db {
+            MigrationDatabase::Storage => crate::storage::STORAGE_MIGRATIONS,
+            MigrationDatabase::State => crate::storage::STATE_MIGRATIONS,
+        };
+
+        match (self, db) {
+            (MigrationOperation::List, _) => {}
+            (MigrationOperation::Up, MigrationDatabase::Storage) => collection.migrate().await?,
+            (MigrationOperation::Up, MigrationDatabase::State) => {
+                collection.migrate_state().await?
+            }
+            (MigrationOperation::Down { target }, _) => collection.rollback(&set, *target).await?,
+        }
+
+        for status in collection.migration_status(&set).await? {
+            println!("{}", status);
+        }
+
+        Ok(())
+    }
+}
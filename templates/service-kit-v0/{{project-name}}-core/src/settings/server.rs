@@ -13,6 +13,10 @@ pub struct Server {
     /// The settings for the server.
     #[clap(flatten)]
     pub settings: NetworkSettings,
+    /// Seed storage with the files configured in `[storage.seed]` before
+    /// serving. Files already seeded once are never seeded again.
+    #[clap(long)]
+    pub seed: bool,
 }
 
 #[derive(Clone, Debug, Parser, Default, EnumString, VariantNames)]
@@ -26,6 +30,8 @@ pub enum ServerMode {
     Web,
     /// Run the server in an api mode.
     Api,
+    /// Run the registered queue workers instead of serving requests.
+    Worker,
 }
 
 impl ServerMode {
@@ -38,6 +44,7 @@ impl ServerMode {
             ServerMode::Full => crate::server::init(config).await?,
             ServerMode::Web => crate::server::web::init(config).await?,
             ServerMode::Api => crate::server::api::init(config).await?,
+            ServerMode::Worker => crate::server::worker::init(config).await?,
         })
     }
 
@@ -0,0 +1,118 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+use crate::storage::{StorageError, StorageImportConflict};
+
+#[derive(Clone, Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub struct Storage {
+    /// What to do with the configured storage.
+    #[clap(subcommand)]
+    pub operation: StorageOperation,
+}
+
+#[derive(Clone, Debug, Parser)]
+#[clap(rename_all = "kebab-case")]
+pub enum StorageOperation {
+    /// Write every stored file, with its metadata, to a zip archive.
+    Export {
+        /// The archive to write.
+        output: PathBuf,
+    },
+    /// Store every file in an archive written by `export`.
+    Import {
+        /// The archive to read.
+        archive: PathBuf,
+        /// What to do with files whose path is already taken.
+        #[clap(long, value_enum, default_value_t)]
+        on_conflict: StorageImportConflict,
+    },
+    /// Copy the database to a new file, safely even while the server is running.
+    Backup {
+        /// The file to write the copy to. Anything already there is replaced.
+        dest: PathBuf,
+    },
+    /// Rebuild the database file to give back space left by deleted files.
+    Vacuum,
+    /// Check the database for corruption.
+    IntegrityCheck,
+    /// Show how large the database is and what it holds.
+    Stats,
+}
+
+impl StorageOperation {
+    pub async fn exec(&self, storage: crate::storage::Storage) -> crate::Result<()> {
+        match self {
+            StorageOperation::Export { output } => {
+                let file = std::fs::File::create(output)?;
+                let files =
+                    crate::storage::export(&*storage, std::io::BufWriter::new(file)).await?;
+
+                println!("Exported {} files to {}", files.len(), output.display());
+            }
+            StorageOperation::Import {
+                archive,
+                on_conflict,
+            } => {
+                let file = std::fs::File::open(archive)?;
+                let summary =
+                    crate::storage::import(&*storage, std::io::BufReader::new(file), *on_conflict)
+                        .await?;
+
+                for path in &summary.skipped {
+                    println!("skipped {}", path);
+                }
+
+                println!(
+                    "Imported {} files from {}",
+                    summary.imported.len(),
+                    archive.display()
+                );
+            }
+            StorageOperation::Backup { dest } => {
+                collection(&storage)?.backup(dest).await?;
+
+                println!("Backed up to {}", dest.display());
+            }
+            StorageOperation::Vacuum => {
+                let storage = collection(&storage)?;
+                let before = storage.stats().await?;
+
+                storage.vacuum().await?;
+
+                let after = storage.stats().await?;
+
+                println!(
+                    "Vacuumed from {} to {} bytes",
+                    before.database_bytes(),
+                    after.database_bytes()
+                );
+            }
+            StorageOperation::IntegrityCheck => {
+                let problems = collection(&storage)?.integrity_check().await?;
+
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+
+                if !problems.is_empty() {
+                    return Err(StorageError::IntegrityCheckFailed(problems.len()))?;
+                }
+
+                println!("ok");
+            }
+            StorageOperation::Stats => println!("{}", collection(&storage)?.stats().await?),
+        }
+
+        Ok(())
+    }
+}
+
+/// The SQLite collection behind `storage`, which only it has to maintain.
+fn collection(
+    storage: &crate::storage::Storage,
+) -> crate::Result<&crate::storage::StorageCollection> {
+    Ok(storage
+        .as_collection()
+        .ok_or(StorageError::MaintenanceUnsupported)?)
+}
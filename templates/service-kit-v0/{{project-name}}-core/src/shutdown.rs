@@ -0,0 +1,165 @@
+//! Stopping the server cleanly. Shutdown begins on SIGINT or SIGTERM, after
+//! which the server stops accepting connections and background tasks stop
+//! taking on new work. Whatever is already underway gets until the drain
+//! timeout to finish before it's cut short.
+
+use std::future::{Future, IntoFuture};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// How shutdown behaves, from the `[shutdown]` section of the configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShutdownOptions {
+    /// How many seconds in-flight requests and background work get to finish
+    /// once shutdown begins.
+    #[serde(default = "ShutdownOptions::default_drain_timeout_seconds")]
+    pub drain_timeout_seconds: u64,
+}
+
+impl Default for ShutdownOptions {
+    fn default() -> Self {
+        Self {
+            drain_timeout_seconds: Self::default_drain_timeout_seconds(),
+        }
+    }
+}
+
+impl ShutdownOptions {
+    fn default_drain_timeout_seconds() -> u64 {
+        30
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_seconds)
+    }
+}
+
+/// Announces that the server is shutting down to everything holding a
+/// clone, including tasks that start watching after it began.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    /// When shutdown began, once it has.
+    started: Arc<watch::Sender<Option<Instant>>>,
+    drain_timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(options: &ShutdownOptions) -> Self {
+        Self {
+            started: Arc::new(watch::channel(None).0),
+            drain_timeout: options.drain_timeout(),
+        }
+    }
+
+    /// Begin shutting down. Only the first call counts.
+    pub fn trigger(&self) {
+        self.started.send_if_modified(|started| match started {
+            Some(_) => false,
+            None => {
+                *started = Some(Instant::now());
+                true
+            }
+        });
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.started.borrow().is_some()
+    }
+
+    /// Resolves once shutdown has begun, straight away if it already has.
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut started = self.started.subscribe();
+
+        async move {
+            // an error means every sender is gone, which is as good as shut down
+            let _ = started.wait_for(Option::is_some).await;
+        }
+    }
+
+    /// Run `task` to the end, unless it's still going once the drain timeout
+    /// has passed since shutdown began. Returns its output if it finished.
+    pub async fn drain<F: IntoFuture>(&self, task: F) -> Option<F::Output> {
+        let mut started = self.started.subscribe();
+        let deadline = async move {
+            let started = match started.wait_for(Option::is_some).await {
+                Ok(started) => *started,
+                Err(_) => None,
+            };
+
+            tokio::time::sleep_until(started.unwrap_or_else(Instant::now) + self.drain_timeout)
+                .await;
+        };
+
+        tokio::select! {
+            biased;
+
+            output = task.into_future() => Some(output),
+            _ = deadline => None,
+        }
+    }
+
+    /// Begin shutting down on SIGINT, or on SIGTERM where there is one, which
+    /// is how systemd stops an installed service.
+    pub async fn trigger_on_signal(self) {
+        #[cfg(unix)]
+        let terminate = async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                }
+                Err(error) => {
+                    tracing::warn!("Unable to listen for SIGTERM: {}", error);
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => tracing::info!("Interrupted, shutting down"),
+            _ = terminate => tracing::info!("Terminated, shutting down"),
+        }
+
+        self.trigger();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Shutdown, ShutdownOptions};
+
+    #[tokio::test]
+    async fn draining() {
+        let shutdown = Shutdown::new(&ShutdownOptions {
+            drain_timeout_seconds: 0,
+        });
+        let waiting = tokio::spawn(shutdown.wait());
+
+        // nothing is cut short before shutdown begins
+        assert_eq!(
+            shutdown
+                .drain(tokio::time::sleep(Duration::from_millis(20)))
+                .await,
+            Some(())
+        );
+        assert!(!shutdown.is_triggered());
+        assert!(!waiting.is_finished());
+
+        shutdown.trigger();
+        shutdown.trigger();
+
+        waiting.await.expect("Waiting task failed");
+        shutdown.wait().await;
+
+        assert!(shutdown.is_triggered());
+        assert_eq!(shutdown.drain(async { 1 }).await, Some(1));
+        assert_eq!(shutdown.drain(std::future::pending::<()>()).await, None);
+    }
+}
@@ -1,17 +1,61 @@
+mod storage_archive;
+mod storage_backend;
+mod storage_batch;
+mod storage_blob;
 mod storage_collection;
+mod storage_entry;
 mod storage_error;
+mod storage_event;
 mod storage_file;
+mod storage_file_attributes;
+mod storage_file_meta;
+mod storage_file_stream;
+mod storage_filesystem;
+mod storage_limits;
+mod storage_maintenance;
+mod storage_memory;
+mod storage_migration;
+mod storage_page;
 mod storage_path;
+mod storage_search;
+mod storage_seed;
+mod storage_sync;
+mod storage_version;
 
+pub use storage_archive::{export, import, StorageImportConflict};
+pub use storage_backend::{Storage, StorageBackend};
+pub use storage_batch::{StorageBatch, StorageBatchContents, StorageBatchOperation};
+pub use storage_blob::file_hash;
+pub(crate) use storage_collection::sql_timestamp;
 pub use storage_collection::StorageCollection;
+pub use storage_entry::StorageEntry;
 pub use storage_error::StorageError;
+pub use storage_event::{StorageEvent, StorageEventKind};
 pub use storage_file::StorageFile;
+pub use storage_file_attributes::StorageFileAttributes;
+pub use storage_file_meta::StorageFileMeta;
+pub use storage_file_stream::StorageFileStream;
+pub use storage_filesystem::FilesystemStorage;
+pub use storage_limits::StorageLimits;
+pub use storage_maintenance::{StorageBackupSchedule, StorageStats};
+pub use storage_memory::MemoryStorage;
+pub use storage_migration::{
+    StorageMigrationSet, StorageMigrationStatus, STATE_MIGRATIONS, STORAGE_MIGRATIONS,
+};
+pub use storage_page::StoragePage;
 pub use storage_path::StoragePath;
+pub use storage_search::StorageSearchHit;
+pub use storage_seed::{seed, StorageSeed};
+pub use storage_sync::{StorageSyncAction, StorageSyncOptions, StorageSyncPlan};
+pub use storage_version::StorageFileVersion;
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
     use tempfile::tempdir;
 
+    use super::{StorageBackend, StorageError, StoragePath};
+
     #[tokio::test]
     async fn file_collections() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = tempdir().expect("Failed to create temp dir");
@@ -32,6 +76,12 @@ mod test {
         assert_eq!(file.size, 13);
         assert_eq!(file.contents, "Hello, world!".as_bytes());
 
+        let meta = collection.stat("/base/hello.md".parse()?).await?;
+
+        assert_eq!(meta.size, 13);
+        assert_eq!(meta.full_path().to_string(), "/base/hello.md");
+        assert_eq!(collection.list_meta("/base".parse()?).await?, vec![meta]);
+
         collection.remove("/base/hello.md".parse()?).await?;
 
         let file = collection.get("/base/hello.md".parse()?).await;
@@ -40,4 +90,1696 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn deduplication() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+        let hash = "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3";
+        let blobs = || async {
+            sqlx::query_as::<_, (String, i64)>("select hash, refcount from blobs order by hash")
+                .fetch_all(&collection.pool)
+                .await
+        };
+
+        collection
+            .insert("/example.md".parse()?, "Hello, world!".as_bytes().into())
+            .await?;
+        collection
+            .insert("/a/hello.md".parse()?, "Hello, world!".as_bytes().into())
+            .await?;
+        collection.link("/b/hello.md".parse()?, hash).await?;
+
+        assert!(collection.contains(hash).await?);
+        assert_eq!(collection.stat("/a/hello.md".parse()?).await?.hash, hash);
+        assert_eq!(collection.get("/b/hello.md".parse()?).await?.hash, hash);
+        assert_eq!(blobs().await?, [(hash.to_string(), 3)]);
+
+        collection.remove("/a/hello.md".parse()?).await?;
+        collection.remove("/example.md".parse()?).await?;
+
+        assert_eq!(blobs().await?, [(hash.to_string(), 1)]);
+
+        collection
+            .insert("/b/hello.md".parse()?, "Goodbye!".as_bytes().into())
+            .await?;
+
+        assert!(!collection.contains(hash).await?);
+        assert!(collection.link("/c/hello.md".parse()?, hash).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streaming() -> Result<(), Box<dyn std::error::Error>> {
+        use futures::TryStreamExt;
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        // spans several chunks, with a partial one at the end
+        let contents: Vec<u8> = (0..1_300_000u32).map(|i| (i % 251) as u8).collect();
+
+        collection
+            .insert_stream("/large.bin".parse()?, contents.as_slice())
+            .await?;
+
+        let stream = collection.open("/large.bin".parse()?).await?;
+
+        assert_eq!(stream.meta.size, contents.len() as i64);
+
+        let chunks: Vec<_> = stream.try_collect().await?;
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), contents);
+
+        // contents stay readable while streamed, even once the file is gone
+        let mut stream = collection.open("/large.bin".parse()?).await?;
+        let hash = stream.meta.hash.clone();
+        let first = stream.try_next().await?.unwrap_or_default();
+
+        collection.remove("/large.bin".parse()?).await?;
+
+        let rest: Vec<_> = stream.try_collect().await?;
+
+        assert_eq!([vec![first], rest].concat().concat(), contents);
+        assert!(!collection.contains(&hash).await?);
+
+        collection
+            .insert("/large.bin".parse()?, "smaller".as_bytes().into())
+            .await?;
+
+        let file = collection.get("/large.bin".parse()?).await?;
+
+        assert_eq!(file.size, 7);
+        assert_eq!(file.contents, "smaller".as_bytes());
+
+        // a file whose contents never turn up is given up on
+        sqlx::query("delete from blobs where hash = $1")
+            .bind(&file.hash)
+            .execute(&collection.pool)
+            .await?;
+
+        assert!(matches!(
+            collection.open("/large.bin".parse()?).await,
+            Err(crate::Error::StorageError(
+                StorageError::IncompleteContents(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staging() -> Result<(), Box<dyn std::error::Error>> {
+        use tokio::io::AsyncWriteExt;
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+        let (mut client, body) = tokio::io::duplex(1024);
+        let upload = tokio::spawn({
+            let collection = collection.clone();
+
+            async move { collection.insert_stream("/slow.md".parse()?, body).await }
+        });
+
+        client.write_all(&[b'a'; 600 * 1024]).await?;
+
+        // other writes go ahead while the upload waits on its client
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            collection.insert("/quick.md".parse()?, "Hello!".as_bytes().into()),
+        )
+        .await??;
+
+        drop(client);
+        upload.await??;
+
+        assert_eq!(collection.stat("/slow.md".parse()?).await?.size, 600 * 1024);
+        assert_eq!(collection.len().await?, 2);
+
+        let staged: (i64,) =
+            sqlx::query_as("select count(*) from blob_chunks where hash like 'staging%'")
+                .fetch_one(&collection.pool)
+                .await?;
+
+        assert_eq!(staged.0, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn listing() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        for path in [
+            "/example.md",
+            "/base/hello.md",
+            "/base/sub/deep.md",
+            "/base/sub/deeper/deepest.md",
+            "/based/other.md",
+        ] {
+            collection
+                .insert(path.parse()?, path.as_bytes().into())
+                .await?;
+        }
+
+        let names =
+            |entries: &[super::StorageEntry]| entries.iter().map(|e| e.path()).collect::<Vec<_>>();
+        let paths = |files: &[super::StorageFileMeta]| {
+            files
+                .iter()
+                .map(|f| f.full_path().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let root = collection.list("/".parse()?).await?;
+
+        assert_eq!(names(&root), ["/base", "/based", "/example.md"]);
+        assert!(root[0].is_directory());
+        assert!(!root[2].is_directory());
+
+        let base = collection.list("/base/".parse()?).await?;
+
+        assert_eq!(names(&base), ["/base/hello.md", "/base/sub"]);
+
+        let walked = collection.walk("/base".parse()?).await?;
+
+        assert_eq!(
+            paths(&walked),
+            [
+                "/base/hello.md",
+                "/base/sub/deep.md",
+                "/base/sub/deeper/deepest.md"
+            ]
+        );
+
+        let mut cursor = None;
+        let mut paged = vec![];
+
+        loop {
+            let page = collection.walk_page("/".parse()?, cursor.take(), 2).await?;
+
+            paged.extend(page.items);
+
+            match page.cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(paged.len(), 5);
+        assert_eq!(paths(&paged), paths(&collection.all().await?));
+
+        let first = collection.list_page("/".parse()?, None, 1).await?;
+        let rest = collection.list_page("/".parse()?, first.cursor, 10).await?;
+
+        assert_eq!(names(&first.items), ["/base"]);
+        assert_eq!(names(&rest.items), ["/based", "/example.md"]);
+        assert!(rest.cursor.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrations() -> Result<(), Box<dyn std::error::Error>> {
+        use super::STORAGE_MIGRATIONS;
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::connect(new_db.clone()).await?;
+        let status = collection.migration_status(&STORAGE_MIGRATIONS).await?;
+
+        assert_eq!(status.len(), STORAGE_MIGRATIONS.migrations.len());
+        assert!(status.iter().all(|s| s.applied_at.is_none()));
+
+        collection.migrate().await?;
+
+        let status = collection.migration_status(&STORAGE_MIGRATIONS).await?;
+
+        assert!(status.iter().all(|s| s.applied_at.is_some()));
+
+        // the state tables stay out of the storage database
+        let tables: Vec<(String,)> =
+            sqlx::query_as("select name from sqlite_master where name = 'cache_entries'")
+                .fetch_all(&collection.pool)
+                .await?;
+
+        assert!(tables.is_empty());
+
+        collection.rollback(&STORAGE_MIGRATIONS, 0).await?;
+
+        let status = collection.migration_status(&STORAGE_MIGRATIONS).await?;
+
+        assert!(status.iter().all(|s| s.applied_at.is_none()));
+
+        // timestamps left by column defaults are brought to the millisecond
+        collection.migrate().await?;
+        collection.rollback(&STORAGE_MIGRATIONS, 13).await?;
+
+        sqlx::query(
+            "insert into files (name, path, size, contents, hash) values ('old.md', '/', 0, x'', 'none')",
+        )
+        .execute(&collection.pool)
+        .await?;
+
+        collection.migrate().await?;
+
+        let (updated_at,): (String,) =
+            sqlx::query_as("select updated_at from files where name = 'old.md'")
+                .fetch_one(&collection.pool)
+                .await?;
+
+        assert!(updated_at.ends_with(".000"), "{}", updated_at);
+
+        // tampering with an applied migration's checksum should refuse to start
+        let collection = super::StorageCollection::file_index(new_db.clone()).await?;
+
+        sqlx::query("update schema_migrations set checksum = 'tampered' where version = 1")
+            .execute(&collection.pool)
+            .await?;
+
+        assert!(super::StorageCollection::file_index(new_db).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn syncing() -> Result<(), Box<dyn std::error::Error>> {
+        use super::{StorageSyncAction, StorageSyncOptions};
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+        let local = temp_dir.path().join("site");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+        std::fs::create_dir_all(local.join("docs"))?;
+        std::fs::write(local.join("index.md"), "Hello")?;
+        std::fs::write(local.join("docs/guide.md"), "Guide")?;
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+        let options = StorageSyncOptions::default();
+        let plan = collection
+            .sync_plan(&local, "/site".parse()?, &options)
+            .await?;
+
+        assert_eq!(
+            plan.actions,
+            [
+                StorageSyncAction::Create {
+                    local: local.join("docs/guide.md"),
+                    remote: "/site/docs/guide.md".to_string(),
+                },
+                StorageSyncAction::Create {
+                    local: local.join("index.md"),
+                    remote: "/site/index.md".to_string(),
+                },
+            ]
+        );
+
+        collection.apply_sync(&plan).await?;
+
+        assert_eq!(
+            collection
+                .get("/site/docs/guide.md".parse()?)
+                .await?
+                .contents,
+            b"Guide"
+        );
+        assert!(collection
+            .sync_plan(&local, "/site".parse()?, &options)
+            .await?
+            .actions
+            .is_empty());
+
+        // same size, different contents, only caught by comparing hashes
+        std::fs::write(local.join("index.md"), "Howdy")?;
+        std::fs::remove_file(local.join("docs/guide.md"))?;
+        collection
+            .insert("/site/extra.md".parse()?, "Extra".as_bytes().into())
+            .await?;
+
+        let options = StorageSyncOptions {
+            delete: true,
+            checksum: true,
+        };
+        let plan = collection
+            .sync_plan(&local, "/site".parse()?, &options)
+            .await?;
+
+        assert_eq!(
+            plan.actions,
+            [
+                StorageSyncAction::Update {
+                    local: local.join("index.md"),
+                    remote: "/site/index.md".to_string(),
+                },
+                StorageSyncAction::Delete {
+                    remote: "/site/docs/guide.md".to_string(),
+                },
+                StorageSyncAction::Delete {
+                    remote: "/site/extra.md".to_string(),
+                },
+            ]
+        );
+
+        collection.apply_sync(&plan).await?;
+
+        let remaining: Vec<_> = collection
+            .walk("/site".parse()?)
+            .await?
+            .iter()
+            .map(|meta| meta.full_path().to_string())
+            .collect();
+
+        assert_eq!(remaining, ["/site/index.md"]);
+        assert_eq!(
+            collection.get("/site/index.md".parse()?).await?.contents,
+            b"Howdy"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn seeding() -> Result<(), Box<dyn std::error::Error>> {
+        use super::storage_seed::{seed, StorageSeed, StorageSeedFile};
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+        let state_db = temp_dir.path().join("state.db");
+        let seed_dir = temp_dir.path().join("seed");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+        std::fs::File::create(&state_db).expect("Failed to create temp db file");
+        std::fs::create_dir_all(seed_dir.join("docs"))?;
+        std::fs::write(seed_dir.join("docs/readme.md"), "Read me")?;
+
+        let collection = super::StorageCollection::file_index(new_db.clone()).await?;
+        let state = super::StorageCollection::state_index(state_db).await?;
+
+        // opening a collection no longer writes anything into it
+        assert_eq!(collection.len().await?, 0);
+
+        let mut manifest = StorageSeed {
+            dir: Some(seed_dir),
+            files: vec![StorageSeedFile {
+                path: "/example.md".to_string(),
+                contents: "Hello, world!".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            seed(&collection, &state, &manifest).await?,
+            ["/docs/readme.md", "/example.md"]
+        );
+
+        // edits and removals survive seeding again
+        collection
+            .insert("/example.md".parse()?, "Edited".as_bytes().into())
+            .await?;
+        collection.remove("/docs/readme.md".parse()?).await?;
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        assert!(seed(&collection, &state, &manifest).await?.is_empty());
+        assert_eq!(
+            collection.get("/example.md".parse()?).await?.contents,
+            b"Edited"
+        );
+        assert!(collection.stat("/docs/readme.md".parse()?).await.is_err());
+
+        // paths that already hold a file are left alone
+        collection
+            .insert("/existing.md".parse()?, "Mine".as_bytes().into())
+            .await?;
+        manifest.files.push(StorageSeedFile {
+            path: "/existing.md".to_string(),
+            contents: "Seeded".to_string(),
+        });
+
+        assert!(seed(&collection, &state, &manifest).await?.is_empty());
+        assert_eq!(
+            collection.get("/existing.md".parse()?).await?.contents,
+            b"Mine"
+        );
+
+        // backends without a database of their own are tracked the same way
+        let memory = super::MemoryStorage::default();
+        let fresh = temp_dir.path().join("fresh.db");
+
+        std::fs::File::create(&fresh).expect("Failed to create temp db file");
+
+        let state = super::StorageCollection::state_index(fresh).await?;
+
+        assert_eq!(seed(&memory, &state, &manifest).await?.len(), 3);
+
+        memory.delete("/example.md".parse()?).await?;
+
+        assert!(seed(&memory, &state, &manifest).await?.is_empty());
+        assert!(memory.stat("/example.md".parse()?).await.is_err());
+
+        Ok(())
+    }
+
+    /// The behaviour every backend has to share.
+    async fn backend_contract(
+        storage: &dyn StorageBackend,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use futures::TryStreamExt;
+
+        storage
+            .put("/docs/readme.md".parse()?, b"Read me".to_vec())
+            .await?;
+        storage
+            .put("/docs/guides/start.md".parse()?, b"Start".to_vec())
+            .await?;
+        storage.put("/top.md".parse()?, b"Top".to_vec()).await?;
+
+        let file = storage.get("/docs/readme.md".parse()?).await?;
+
+        assert_eq!(file.contents, b"Read me");
+        assert_eq!(file.meta(), storage.stat("/docs/readme.md".parse()?).await?);
+        assert_eq!(
+            file.hash,
+            "4d740af9c9c049709697fcc27549d4ac3183246fd8bf6be76fb88ff739914e74"
+        );
+
+        let names = |entries: Vec<super::StorageEntry>| {
+            entries.iter().map(|e| e.path()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(storage.list("/".parse()?).await?),
+            ["/docs", "/top.md"]
+        );
+        assert_eq!(
+            names(storage.list("/docs".parse()?).await?),
+            ["/docs/guides", "/docs/readme.md"]
+        );
+
+        let first = storage.list_page("/docs".parse()?, None, 1).await?;
+        let rest = storage
+            .list_page("/docs".parse()?, first.cursor.clone(), 10)
+            .await?;
+
+        assert_eq!(names(first.items), ["/docs/guides"]);
+        assert_eq!(names(rest.items), ["/docs/readme.md"]);
+        assert!(rest.cursor.is_none());
+
+        let walked: Vec<_> = storage
+            .walk("/".parse()?)
+            .await?
+            .iter()
+            .map(|meta| meta.full_path().to_string())
+            .collect();
+
+        assert_eq!(
+            walked,
+            ["/top.md", "/docs/readme.md", "/docs/guides/start.md"]
+        );
+
+        let page = storage.walk_page("/".parse()?, None, 1).await?;
+        let rest = storage.walk_page("/".parse()?, page.cursor, 10).await?;
+
+        assert_eq!(page.items.len() + rest.items.len(), 3);
+
+        let streamed: Vec<_> = storage
+            .open("/top.md".parse()?)
+            .await?
+            .try_collect()
+            .await?;
+
+        assert_eq!(streamed.concat(), b"Top");
+
+        storage.put("/top.md".parse()?, b"Changed".to_vec()).await?;
+
+        assert_eq!(storage.get("/top.md".parse()?).await?.contents, b"Changed");
+
+        // a write tells whether it made the file or replaced it
+        let plain = super::StorageFileAttributes::default();
+
+        assert!(
+            storage
+                .put_with("/new.md".parse()?, &mut "New".as_bytes(), plain.clone())
+                .await?
+        );
+        assert!(
+            !storage
+                .put_with("/new.md".parse()?, &mut "Newer".as_bytes(), plain)
+                .await?
+        );
+
+        storage.delete("/new.md".parse()?).await?;
+
+        storage.delete("/docs/guides/start.md".parse()?).await?;
+
+        assert_eq!(
+            names(storage.list("/docs".parse()?).await?),
+            ["/docs/readme.md"]
+        );
+        assert!(storage.get("/docs/guides/start.md".parse()?).await.is_err());
+        assert!(storage
+            .delete("/docs/guides/start.md".parse()?)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn backends() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        backend_contract(&super::StorageCollection::file_index(new_db).await?).await?;
+        backend_contract(&super::FilesystemStorage::new(
+            temp_dir.path().join("files"),
+        )?)
+        .await?;
+        backend_contract(&super::MemoryStorage::default()).await?;
+
+        // the filesystem backend never reaches outside its root
+        let files = super::FilesystemStorage::new(temp_dir.path().join("files"))?;
+
+        assert!(files
+            .put(super::StoragePath::new("/../escape.md".into()), vec![])
+            .await
+            .is_err());
+
+        // an interrupted write leaves the file as it was, and nothing behind
+        files.put("/kept.md".parse()?, b"kept".to_vec()).await?;
+
+        let hash = files.stat("/kept.md".parse()?).await?.hash;
+        let mut failing = tokio::io::AsyncReadExt::chain(
+            b"partly".as_slice(),
+            tokio_util::io::StreamReader::new(futures::stream::iter([Err::<bytes::Bytes, _>(
+                std::io::Error::other("client went away"),
+            )])),
+        );
+
+        assert!(files
+            .put_stream("/kept.md".parse()?, &mut failing)
+            .await
+            .is_err());
+        assert_eq!(files.get("/kept.md".parse()?).await?.contents, b"kept");
+        assert!(std::fs::read_dir(temp_dir.path().join("files"))?
+            .all(|entry| entry
+                .is_ok_and(|entry| !entry.file_name().to_string_lossy().starts_with('.'))));
+
+        // remembered hashes follow the contents
+        files.put("/kept.md".parse()?, b"changed".to_vec()).await?;
+
+        assert_ne!(files.stat("/kept.md".parse()?).await?.hash, hash);
+        assert_eq!(
+            files.stat("/kept.md".parse()?).await?.hash,
+            files.get("/kept.md".parse()?).await?.hash
+        );
+
+        Ok(())
+    }
+
+    /// Batches apply completely or not at all, whatever the backend.
+    async fn batch_contract(
+        storage: &dyn StorageBackend,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use super::StorageBatch;
+
+        storage
+            .put("/site/old.md".parse()?, b"Old".to_vec())
+            .await?;
+        storage
+            .put("/site/keep.md".parse()?, b"Keep".to_vec())
+            .await?;
+
+        let failing = StorageBatch::new()
+            .insert("/site/new.md".parse()?, b"New".to_vec())
+            .rename("/site/keep.md".parse()?, "/site/moved.md".parse()?, false)
+            .remove("/site/missing.md".parse()?);
+
+        assert!(storage.apply_batch(failing).await.is_err());
+
+        let paths = || async {
+            Ok::<_, crate::Error>(
+                storage
+                    .walk("/site".parse()?)
+                    .await?
+                    .iter()
+                    .map(|meta| meta.full_path().to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(paths().await?, ["/site/keep.md", "/site/old.md"]);
+
+        let deploy = StorageBatch::new()
+            .insert("/site/new.md".parse()?, b"New".to_vec())
+            .rename("/site/keep.md".parse()?, "/site/old.md".parse()?, true)
+            .remove("/site/new.md".parse()?)
+            .insert("/site/index.md".parse()?, b"Index".to_vec());
+
+        storage.apply_batch(deploy).await?;
+
+        assert_eq!(paths().await?, ["/site/index.md", "/site/old.md"]);
+        assert_eq!(
+            storage.get("/site/old.md".parse()?).await?.contents,
+            b"Keep"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batches() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        batch_contract(&collection).await?;
+        batch_contract(&super::FilesystemStorage::new(
+            temp_dir.path().join("files"),
+        )?)
+        .await?;
+        batch_contract(&super::MemoryStorage::default()).await?;
+
+        // nothing is left referring to contents the failed batch wrote
+        let blobs: Vec<(i64,)> = sqlx::query_as("select refcount from blobs order by hash")
+            .fetch_all(&collection.pool)
+            .await?;
+
+        assert_eq!(blobs, [(1,), (1,)]);
+
+        Ok(())
+    }
+
+    /// Renames, copies and directory moves, whatever the backend.
+    async fn move_contract(storage: &dyn StorageBackend) -> Result<(), Box<dyn std::error::Error>> {
+        use super::StorageError;
+
+        let conflict = |result: crate::Result<()>| {
+            matches!(
+                result,
+                Err(crate::Error::StorageError(StorageError::FileExists(_)))
+            )
+        };
+        let paths = || async {
+            Ok::<_, crate::Error>(
+                storage
+                    .walk("/".parse()?)
+                    .await?
+                    .iter()
+                    .map(|meta| meta.full_path().to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        storage.put("/a.md".parse()?, b"A".to_vec()).await?;
+        storage.put("/b.md".parse()?, b"B".to_vec()).await?;
+
+        let created = storage.stat("/a.md".parse()?).await?.created_at;
+
+        storage
+            .rename("/a.md".parse()?, "/docs/a.md".parse()?, false)
+            .await?;
+
+        assert_eq!(
+            storage.stat("/docs/a.md".parse()?).await?.created_at,
+            created
+        );
+        assert!(conflict(
+            storage
+                .rename("/b.md".parse()?, "/docs/a.md".parse()?, false)
+                .await
+        ));
+
+        storage
+            .copy("/b.md".parse()?, "/docs/b.md".parse()?, false)
+            .await?;
+
+        assert!(conflict(
+            storage
+                .copy("/b.md".parse()?, "/docs/a.md".parse()?, false)
+                .await
+        ));
+
+        storage
+            .copy("/b.md".parse()?, "/docs/a.md".parse()?, true)
+            .await?;
+
+        assert_eq!(storage.get("/docs/a.md".parse()?).await?.contents, b"B");
+        assert_eq!(paths().await?, ["/b.md", "/docs/a.md", "/docs/b.md"]);
+
+        // a directory move either conflicts up front or moves everything
+        storage
+            .put("/archive/a.md".parse()?, b"Old".to_vec())
+            .await?;
+
+        assert!(conflict(
+            storage
+                .move_directory("/docs".parse()?, "/archive".parse()?, false)
+                .await
+        ));
+        assert_eq!(
+            storage.get("/archive/a.md".parse()?).await?.contents,
+            b"Old"
+        );
+
+        storage
+            .move_directory("/docs".parse()?, "/archive".parse()?, true)
+            .await?;
+
+        assert_eq!(paths().await?, ["/b.md", "/archive/a.md", "/archive/b.md"]);
+        assert_eq!(storage.get("/archive/a.md".parse()?).await?.contents, b"B");
+
+        assert!(matches!(
+            storage
+                .move_directory("/archive".parse()?, "/archive/nested".parse()?, false)
+                .await,
+            Err(crate::Error::StorageError(
+                StorageError::MoveIntoSelf { .. }
+            ))
+        ));
+        assert!(matches!(
+            storage
+                .move_directory("/missing".parse()?, "/elsewhere".parse()?, false)
+                .await,
+            Err(crate::Error::StorageError(StorageError::DirectoryNotFound(
+                _
+            )))
+        ));
+
+        // moving a directory up a level over paths it's vacating isn't a conflict
+        storage.put("/up/up/up.md".parse()?, b"Up".to_vec()).await?;
+        storage.put("/up/up.md".parse()?, b"Up".to_vec()).await?;
+        storage
+            .move_directory("/up".parse()?, "/".parse()?, false)
+            .await?;
+
+        assert_eq!(
+            paths().await?,
+            [
+                "/b.md",
+                "/up.md",
+                "/archive/a.md",
+                "/archive/b.md",
+                "/up/up.md"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn moving() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        move_contract(&super::StorageCollection::file_index(new_db).await?).await?;
+        move_contract(&super::FilesystemStorage::new(
+            temp_dir.path().join("files"),
+        )?)
+        .await?;
+        move_contract(&super::MemoryStorage::default()).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn versions() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db)
+            .await?
+            .with_history(2);
+        let path: super::StoragePath = "/notes/v.md".parse()?;
+
+        for contents in ["one", "two", "three", "four", "four"] {
+            collection.insert(path.clone(), contents.into()).await?;
+        }
+
+        // only the newest two are kept, and rewriting the same contents adds nothing
+        let versions = collection.versions(path.clone()).await?;
+
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            [3, 2]
+        );
+        assert_eq!(
+            collection.get_version(path.clone(), 3).await?.contents,
+            b"three"
+        );
+        assert!(matches!(
+            collection.get_version(path.clone(), 1).await,
+            Err(crate::Error::StorageError(
+                super::StorageError::VersionNotFound { .. }
+            ))
+        ));
+
+        // a restore keeps what it replaced, so it can be undone
+        collection.restore(path.clone(), 2).await?;
+        assert_eq!(collection.get(path.clone()).await?.contents, b"two");
+
+        collection.restore(path.clone(), 4).await?;
+        assert_eq!(collection.get(path.clone()).await?.contents, b"four");
+
+        // removed files can be brought back
+        collection.remove(path.clone()).await?;
+        assert!(collection.get(path.clone()).await.is_err());
+
+        let latest = collection.versions(path.clone()).await?[0].version;
+
+        collection.restore(path.clone(), latest).await?;
+        assert_eq!(collection.get(path.clone()).await?.contents, b"four");
+
+        // "four" is held by the file and a version, "two" by a version only
+        let blobs: Vec<(i64,)> = sqlx::query_as("select refcount from blobs order by refcount")
+            .fetch_all(&collection.pool)
+            .await?;
+
+        assert_eq!(blobs, [(1,), (2,)]);
+
+        // without history nothing is kept
+        let collection = collection.with_history(0);
+        let other: super::StoragePath = "/other.md".parse()?;
+
+        collection.insert(other.clone(), "a".into()).await?;
+        collection.insert(other.clone(), "b".into()).await?;
+
+        assert!(collection.versions(other).await?.is_empty());
+
+        // backends without history have no versions to restore
+        let memory = super::MemoryStorage::default();
+
+        memory.put(path.clone(), "one".into()).await?;
+        memory.put(path.clone(), "two".into()).await?;
+
+        assert!(memory.versions(path.clone()).await?.is_empty());
+        assert!(memory.restore(path, 1).await.is_err());
+
+        Ok(())
+    }
+
+    /// Modification times, whatever the backend.
+    async fn timestamp_contract(
+        storage: &dyn StorageBackend,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let pause = || tokio::time::sleep(std::time::Duration::from_millis(20));
+        let (a, b): (super::StoragePath, super::StoragePath) =
+            ("/t/a.md".parse()?, "/t/b.md".parse()?);
+
+        storage.put(a.clone(), "first".into()).await?;
+        pause().await;
+
+        let mark = chrono::Utc::now();
+
+        pause().await;
+        storage.put(b.clone(), "second".into()).await?;
+        pause().await;
+        storage.put(a.clone(), "rewritten".into()).await?;
+
+        let meta = storage.stat(a.clone()).await?;
+
+        assert!(meta.updated_at > mark);
+        assert!(meta.updated_at >= storage.stat(b.clone()).await?.updated_at);
+
+        let changed = storage.modified_since(mark).await?;
+
+        assert_eq!(
+            changed
+                .iter()
+                .map(|meta| meta.full_path().to_string())
+                .collect::<Vec<_>>(),
+            [b.to_string(), a.to_string()]
+        );
+        assert!(storage.modified_since(meta.updated_at).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn timestamps() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        timestamp_contract(&collection).await?;
+        timestamp_contract(&super::FilesystemStorage::new(
+            temp_dir.path().join("files"),
+        )?)
+        .await?;
+        timestamp_contract(&super::MemoryStorage::default()).await?;
+
+        // rewriting a file keeps when it was created
+        let meta = collection.stat("/t/a.md".parse()?).await?;
+
+        assert!(meta.created_at < meta.updated_at);
+
+        Ok(())
+    }
+
+    /// Content types, metadata and tags, for backends that keep them.
+    async fn attribute_contract(
+        storage: &dyn StorageBackend,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let attributes = super::StorageFileAttributes {
+            content_type: Some("text/plain".to_string()),
+            metadata: [("cache-control".to_string(), "max-age=60".to_string())].into(),
+            tags: ["draft".to_string(), "notes".to_string()].into(),
+        };
+        let path: StoragePath = "/notes/README".parse()?;
+
+        storage
+            .put_with(path.clone(), &mut "Read me".as_bytes(), attributes.clone())
+            .await?;
+
+        assert_eq!(storage.stat(path.clone()).await?.attributes, attributes);
+        assert_eq!(
+            storage
+                .stat(path.clone())
+                .await?
+                .attributes
+                .content_type_for("README"),
+            "text/plain"
+        );
+
+        // new contents keep the attributes, and so do moves and copies
+        storage.put(path.clone(), "Read me again".into()).await?;
+        storage
+            .rename(path.clone(), "/notes/moved".parse()?, false)
+            .await?;
+        storage
+            .copy("/notes/moved".parse()?, "/copied".parse()?, false)
+            .await?;
+        storage.put("/untagged.md".parse()?, "plain".into()).await?;
+
+        assert_eq!(
+            storage.get("/copied".parse()?).await?.attributes,
+            attributes
+        );
+        assert_eq!(
+            storage
+                .find_by_tag("draft")
+                .await?
+                .iter()
+                .map(|meta| meta.full_path().to_string())
+                .collect::<Vec<_>>(),
+            ["/copied", "/notes/moved"]
+        );
+
+        storage
+            .set_attributes("/copied".parse()?, Default::default())
+            .await?;
+
+        assert_eq!(storage.find_by_tag("draft").await?.len(), 1);
+        assert!(storage.find_by_tag("missing").await?.is_empty());
+        assert!(storage
+            .set_attributes("/nowhere.md".parse()?, attributes)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn attributes() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        attribute_contract(&super::StorageCollection::file_index(new_db).await?).await?;
+        attribute_contract(&super::MemoryStorage::default()).await?;
+
+        // the filesystem backend keeps contents only, and writes nothing
+        // when asked to keep more
+        let files = super::FilesystemStorage::new(temp_dir.path().join("files"))?;
+        let tagged = super::StorageFileAttributes {
+            tags: ["draft".to_string()].into(),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            files
+                .put_with("/a.md".parse()?, &mut "a".as_bytes(), tagged.clone())
+                .await,
+            Err(crate::Error::StorageError(
+                StorageError::AttributesUnsupported
+            ))
+        ));
+        assert!(files.stat("/a.md".parse()?).await.is_err());
+
+        files
+            .put_with("/a.md".parse()?, &mut "a".as_bytes(), Default::default())
+            .await?;
+
+        assert!(files.stat("/a.md".parse()?).await?.attributes.is_empty());
+        assert!(matches!(
+            files.set_attributes("/a.md".parse()?, tagged).await,
+            Err(crate::Error::StorageError(
+                StorageError::AttributesUnsupported
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn searching() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db.clone()).await?;
+        let runbook = "# Restarting\n\nDrain the queue, then restart the worker.";
+
+        collection
+            .insert("/runbooks/restart.md".parse()?, runbook.into())
+            .await?;
+        collection
+            .insert("/copy/restart.md".parse()?, runbook.into())
+            .await?;
+        collection
+            .insert(
+                "/runbooks/deploy.md".parse()?,
+                "Deploy, then restart twice: restart restart".into(),
+            )
+            .await?;
+        collection
+            .insert("/binary.bin".parse()?, b"restart\0worker".to_vec())
+            .await?;
+
+        let paths = |hits: Vec<super::StorageSearchHit>| {
+            hits.iter()
+                .map(|hit| hit.file.full_path().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // shared contents find every file, binaries are never indexed
+        assert_eq!(
+            paths(collection.search("restart", 10).await?),
+            [
+                "/runbooks/deploy.md",
+                "/copy/restart.md",
+                "/runbooks/restart.md"
+            ]
+        );
+        assert_eq!(
+            paths(collection.search("drain worker", 10).await?),
+            ["/copy/restart.md", "/runbooks/restart.md"]
+        );
+        assert_eq!(collection.search("restart", 1).await?.len(), 1);
+        assert!(collection.search("queue", 10).await?[0]
+            .snippet
+            .contains("[queue]"));
+
+        // operators and stray quotes are just words
+        assert!(collection.search("\"drain OR", 10).await?.is_empty());
+        assert!(collection.search("   ", 10).await?.is_empty());
+
+        // removed contents drop out of the index
+        collection.remove("/runbooks/deploy.md".parse()?).await?;
+        assert_eq!(collection.search("twice", 10).await?.len(), 0);
+
+        // blobs stored before the index existed are picked up on open
+        sqlx::query("delete from blob_text")
+            .execute(&collection.pool)
+            .await?;
+        sqlx::query("update blobs set searchable = null")
+            .execute(&collection.pool)
+            .await?;
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+
+        assert_eq!(collection.search("drain", 10).await?.len(), 2);
+        assert!(matches!(
+            super::MemoryStorage::default().search("drain", 10).await,
+            Err(crate::Error::StorageError(StorageError::SearchUnsupported))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn limits() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let limits = super::StorageLimits {
+            all: super::storage_limits::StorageQuota {
+                max_file_size: Some(8),
+                max_total_size: Some(20),
+                max_files: Some(4),
+            },
+            prefixes: vec![(
+                "/small".parse()?,
+                super::storage_limits::StorageQuota {
+                    max_file_size: Some(4),
+                    max_files: Some(1),
+                    ..Default::default()
+                },
+            )],
+        };
+        let collection = super::StorageCollection::file_index(new_db)
+            .await?
+            .with_limits(limits);
+
+        // prefixes are checked as the configuration is read, not on each write
+        let configured: super::StorageLimits =
+            serde_json::from_str(r#"{ "max_files": 2, "prefixes": { "/a/../b": {} } }"#)?;
+
+        assert_eq!(configured.prefixes[0].0, "/b".parse()?);
+        assert!(
+            serde_json::from_str::<super::StorageLimits>(r#"{ "prefixes": { "b": {} } }"#).is_err()
+        );
+        assert!(
+            serde_json::from_str::<super::StorageLimits>(r#"{ "prefixes": { "/../b": {} } }"#)
+                .is_err()
+        );
+
+        assert!(matches!(
+            collection.insert("/big.md".parse()?, vec![b'a'; 9]).await,
+            Err(crate::Error::StorageError(StorageError::FileTooLarge {
+                max: 8,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            collection
+                .insert("/small/a.md".parse()?, vec![b'a'; 5])
+                .await,
+            Err(crate::Error::StorageError(StorageError::FileTooLarge {
+                max: 4,
+                ..
+            }))
+        ));
+        assert!(collection.walk("/".parse()?).await?.is_empty());
+
+        collection.insert("/a.md".parse()?, vec![b'a'; 8]).await?;
+        collection.insert("/b.md".parse()?, vec![b'b'; 8]).await?;
+
+        // overwriting only counts the difference
+        collection.insert("/b.md".parse()?, vec![b'c'; 6]).await?;
+
+        assert!(matches!(
+            collection.insert("/c.md".parse()?, vec![b'c'; 7]).await,
+            Err(crate::Error::StorageError(StorageError::QuotaExceeded {
+                max: 20,
+                ..
+            }))
+        ));
+
+        // contents are only read until they go over
+        assert!(matches!(
+            collection
+                .insert_stream("/c.md".parse()?, tokio::io::repeat(b'c'))
+                .await,
+            Err(crate::Error::StorageError(StorageError::QuotaExceeded {
+                max: 20,
+                ..
+            }))
+        ));
+
+        collection
+            .insert("/small/a.md".parse()?, vec![b'a'; 4])
+            .await?;
+
+        assert!(matches!(
+            collection
+                .insert("/small/b.md".parse()?, vec![b'b'; 1])
+                .await,
+            Err(crate::Error::StorageError(StorageError::FileLimitReached {
+                max: 1,
+                ..
+            }))
+        ));
+
+        // moving into a directory counts against it, moving within one doesn't
+        assert!(matches!(
+            collection
+                .rename("/b.md".parse()?, "/small/b.md".parse()?, false)
+                .await,
+            Err(crate::Error::StorageError(StorageError::FileTooLarge {
+                max: 4,
+                ..
+            }))
+        ));
+
+        collection
+            .rename("/small/a.md".parse()?, "/small/c.md".parse()?, false)
+            .await?;
+        collection.insert("/d.md".parse()?, vec![b'd'; 1]).await?;
+
+        assert!(matches!(
+            collection.insert("/e.md".parse()?, vec![]).await,
+            Err(crate::Error::StorageError(StorageError::FileLimitReached {
+                max: 4,
+                ..
+            }))
+        ));
+
+        // a batch that goes over leaves nothing behind
+        let batch = super::StorageBatch::new()
+            .remove("/d.md".parse()?)
+            .insert("/e.md".parse()?, vec![b'e'; 2])
+            .insert("/f.md".parse()?, vec![b'f'; 1]);
+
+        assert!(collection.apply_batch(batch).await.is_err());
+        assert_eq!(collection.walk("/".parse()?).await?.len(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn archives() -> Result<(), Box<dyn std::error::Error>> {
+        use super::StorageImportConflict;
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let (source_db, target_db) = (
+            temp_dir.path().join("source.db"),
+            temp_dir.path().join("target.db"),
+        );
+
+        std::fs::File::create(&source_db).expect("Failed to create temp db file");
+        std::fs::File::create(&target_db).expect("Failed to create temp db file");
+
+        let source = super::StorageCollection::file_index(source_db).await?;
+        let target = super::StorageCollection::file_index(target_db).await?;
+        let tagged = super::StorageFileAttributes {
+            content_type: Some("text/markdown".to_string()),
+            metadata: [("owner".to_string(), "ops".to_string())].into(),
+            tags: ["runbook".to_string()].into(),
+        };
+
+        source
+            .insert_with("/docs/a.md".parse()?, "a".as_bytes(), &tagged)
+            .await?;
+        source.insert("/docs/b.md".parse()?, b"b".to_vec()).await?;
+        source.insert("/copy.md".parse()?, b"a".to_vec()).await?;
+
+        let mut archive = std::io::Cursor::new(vec![]);
+        let exported = super::export(&source, &mut archive).await?;
+        let archive = archive.into_inner();
+        let open = || std::io::Cursor::new(archive.clone());
+
+        assert_eq!(exported.len(), 3);
+
+        // everything comes back as it was, timestamps and attributes included
+        let imported = super::import(&target, open(), StorageImportConflict::Fail).await?;
+
+        assert_eq!(imported.imported.len(), 3);
+        assert_eq!(target.all().await?, source.all().await?);
+        assert_eq!(target.get("/docs/a.md".parse()?).await?.contents, b"a");
+
+        // conflicts abandon the import, are skipped or overwritten
+        target
+            .insert("/docs/b.md".parse()?, b"changed".to_vec())
+            .await?;
+        target.remove("/copy.md".parse()?).await?;
+
+        assert!(matches!(
+            super::import(&target, open(), StorageImportConflict::Fail).await,
+            Err(crate::Error::StorageError(StorageError::FileExists(_)))
+        ));
+        assert!(target.stat("/copy.md".parse()?).await.is_err());
+
+        let skipped = super::import(&target, open(), StorageImportConflict::Skip).await?;
+
+        assert_eq!(skipped.imported, ["/copy.md"]);
+        assert_eq!(skipped.skipped, ["/docs/a.md", "/docs/b.md"]);
+        assert_eq!(
+            target.get("/docs/b.md".parse()?).await?.contents,
+            b"changed"
+        );
+
+        super::import(&target, open(), StorageImportConflict::Overwrite).await?;
+
+        assert_eq!(target.all().await?, source.all().await?);
+
+        // any backend can be exported from or imported into
+        let memory = super::MemoryStorage::default();
+
+        super::import(&memory, open(), StorageImportConflict::Fail).await?;
+
+        assert_eq!(memory.walk("/".parse()?).await?, source.all().await?);
+
+        // contents that don't match the manifest are refused before anything is stored
+        let mut tampered = std::io::Cursor::new(vec![]);
+        let mut writer = super::storage_archive::ArchiveWriter::new(&mut tampered);
+
+        writer.start(&source.stat("/copy.md".parse()?).await?)?;
+        writer.write(b"a")?;
+        writer.start(&source.stat("/docs/a.md".parse()?).await?)?;
+        writer.write(b"a, and then some")?;
+        writer.finish()?;
+
+        let empty = super::MemoryStorage::default();
+
+        assert!(matches!(
+            super::import(
+                &empty,
+                std::io::Cursor::new(tampered.into_inner()),
+                StorageImportConflict::Fail
+            )
+            .await,
+            Err(crate::Error::StorageError(StorageError::ArchiveMismatch(_)))
+        ));
+        assert!(empty.walk("/".parse()?).await?.is_empty());
+
+        // an archive without a manifest isn't one of ours
+        let mut foreign = std::io::Cursor::new(vec![]);
+        let mut zip = zip::ZipWriter::new(&mut foreign);
+
+        zip.start_file("a.md", zip::write::SimpleFileOptions::default())?;
+        zip.finish()?;
+
+        assert!(matches!(
+            super::import(
+                &target,
+                std::io::Cursor::new(foreign.into_inner()),
+                StorageImportConflict::Skip
+            )
+            .await,
+            Err(crate::Error::StorageError(StorageError::InvalidArchive(_)))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn maintenance() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db.clone())
+            .await?
+            .with_history(1);
+
+        collection
+            .insert("/a.md".parse()?, vec![b'a'; 4096])
+            .await?;
+        collection
+            .insert("/a.md".parse()?, vec![b'b'; 4096])
+            .await?;
+        collection
+            .insert("/copy.md".parse()?, vec![b'b'; 4096])
+            .await?;
+
+        let stats = collection.stats().await?;
+
+        assert_eq!((stats.files, stats.total_bytes), (2, 8192));
+        assert_eq!((stats.blobs, stats.stored_bytes), (2, 8192));
+        assert_eq!(stats.versions, 1);
+        assert!(collection.integrity_check().await?.is_empty());
+
+        // a backup is a complete database of its own, taken while in use
+        let backup_db = temp_dir.path().join("backup.db");
+
+        // whatever an interrupted backup left behind is written over
+        std::fs::write(temp_dir.path().join("backup.db.partial"), b"torn")?;
+        collection.backup(&backup_db).await?;
+
+        assert!(!temp_dir.path().join("backup.db.partial").exists());
+        collection.remove("/copy.md".parse()?).await?;
+
+        let backup = super::StorageCollection::connect(backup_db).await?;
+
+        assert_eq!(backup.stats().await?.files, 2);
+        assert_eq!(
+            backup.get("/copy.md".parse()?).await?.contents,
+            vec![b'b'; 4096]
+        );
+
+        collection.vacuum().await?;
+
+        assert_eq!(collection.stats().await?.free_pages, 0);
+
+        // scheduled backups keep only the newest few
+        let schedule = super::StorageBackupSchedule {
+            dir: temp_dir.path().join("backups"),
+            every_minutes: 60,
+            keep: 2,
+        };
+
+        std::fs::create_dir(&schedule.dir)?;
+
+        for stamp in ["20260101T000000Z", "20260102T000000Z", "20260103T000000Z"] {
+            std::fs::File::create(schedule.dir.join(format!("new-{}.db", stamp)))?;
+        }
+
+        std::fs::File::create(schedule.dir.join("other-20200101T000000Z.db"))?;
+        std::fs::File::create(schedule.dir.join("new-20260104T000000Z.db.partial"))?;
+
+        let pruned = schedule.prune(&new_db).await?;
+
+        assert_eq!(pruned, [schedule.dir.join("new-20260101T000000Z.db")]);
+        assert!(schedule.next_path(&new_db).starts_with(&schedule.dir));
+        assert_eq!(std::fs::read_dir(&schedule.dir)?.count(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watching() -> Result<(), Box<dyn std::error::Error>> {
+        use super::{StorageBatch, StorageEventKind};
+        use futures::StreamExt;
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let new_db = temp_dir.path().join("new.db");
+
+        std::fs::File::create(&new_db).expect("Failed to create temp db file");
+
+        let collection = super::StorageCollection::file_index(new_db).await?;
+        let mut events = collection.subscribe();
+        let mut docs = StorageBackend::watch(&collection, "/docs".parse()?)?;
+
+        collection.insert("/a.md".parse()?, b"A".to_vec()).await?;
+        collection.insert("/a.md".parse()?, b"AA".to_vec()).await?;
+        // only attributes that differ from what the file has are a change
+        collection
+            .set_attributes("/a.md".parse()?, &Default::default())
+            .await?;
+        collection
+            .set_attributes(
+                "/a.md".parse()?,
+                &super::StorageFileAttributes {
+                    tags: ["draft".to_string()].into(),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        collection
+            .rename("/a.md".parse()?, "/docs/b.md".parse()?, false)
+            .await?;
+        // new contents and attributes together are a single change
+        collection
+            .insert_with(
+                "/docs/c.md".parse()?,
+                &mut "C".as_bytes(),
+                &Default::default(),
+            )
+            .await?;
+        collection.remove("/docs/b.md".parse()?).await?;
+
+        // nothing is announced for a batch that doesn't go through
+        let failing = StorageBatch::new()
+            .insert("/docs/d.md".parse()?, b"D".to_vec())
+            .remove("/missing.md".parse()?);
+
+        assert!(collection.apply_batch(failing).await.is_err());
+
+        collection
+            .insert("/last.md".parse()?, b"Last".to_vec())
+            .await?;
+
+        let mut seen = vec![];
+
+        while let Ok(event) = events.try_recv() {
+            seen.push((event.kind, event.path, event.size));
+        }
+
+        assert_eq!(
+            seen,
+            [
+                (StorageEventKind::Created, "/a.md".to_string(), 1),
+                (StorageEventKind::Updated, "/a.md".to_string(), 2),
+                (StorageEventKind::Updated, "/a.md".to_string(), 2),
+                (StorageEventKind::Deleted, "/a.md".to_string(), 2),
+                (StorageEventKind::Created, "/docs/b.md".to_string(), 2),
+                (StorageEventKind::Created, "/docs/c.md".to_string(), 1),
+                (StorageEventKind::Deleted, "/docs/b.md".to_string(), 2),
+                (StorageEventKind::Created, "/last.md".to_string(), 4),
+            ]
+        );
+
+        // a watcher only hears about its own directory
+        let mut watched = vec![];
+
+        for _ in 0..3 {
+            let event = docs.next().await.expect("Watch ended early");
+
+            watched.push((event.kind, event.path));
+        }
+
+        assert_eq!(
+            watched,
+            [
+                (StorageEventKind::Created, "/docs/b.md".to_string()),
+                (StorageEventKind::Created, "/docs/c.md".to_string()),
+                (StorageEventKind::Deleted, "/docs/b.md".to_string()),
+            ]
+        );
+
+        assert!(matches!(
+            StorageBackend::watch(&super::MemoryStorage::default(), "/".parse()?),
+            Err(crate::Error::StorageError(StorageError::WatchUnsupported))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_normalization() {
+        let normalized = [
+            ("/a/../b.md", "/b.md"),
+            ("//a//b", "/a/b"),
+            ("/a/b/", "/a/b"),
+            ("\\a\\b.md", "/a/b.md"),
+            ("/./a/./b", "/a/b"),
+            ("/", "/"),
+            ("/..a/b..", "/..a/b.."),
+            ("a/b/../c", "a/c"),
+        ];
+
+        for (raw, expected) in normalized {
+            assert_eq!(raw.parse::<StoragePath>().unwrap().to_string(), expected);
+        }
+
+        let long_name = "a".repeat(super::storage_path::MAX_NAME_LENGTH + 1);
+        let long_path = format!("/{}", ["a"; 3000].join("/"));
+
+        assert!(matches!(
+            "".parse::<StoragePath>(),
+            Err(StorageError::EmptyPath)
+        ));
+        assert!(matches!(
+            "a/..".parse::<StoragePath>(),
+            Err(StorageError::EmptyPath)
+        ));
+        assert!(matches!(
+            "/a/../..".parse::<StoragePath>(),
+            Err(StorageError::PathEscapesRoot(_))
+        ));
+        assert!(matches!(
+            "/a\0b".parse::<StoragePath>(),
+            Err(StorageError::InvalidPathCharacter {
+                character: '\0',
+                ..
+            })
+        ));
+        assert!(matches!(
+            format!("/{}", long_name).parse::<StoragePath>(),
+            Err(StorageError::NameTooLong { .. })
+        ));
+        assert!(matches!(
+            long_path.parse::<StoragePath>(),
+            Err(StorageError::PathTooLong { .. })
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let path = std::path::PathBuf::from(std::ffi::OsStr::from_bytes(b"/a\xff"));
+
+            assert!(matches!(
+                StoragePath::try_from(path),
+                Err(StorageError::NonUtf8Path(_))
+            ));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn normalized_paths_are_stable(raw in "[/\\\\.ab]{1,24}") {
+            if let Ok(path) = raw.parse::<StoragePath>() {
+                let normalized = path.to_string();
+
+                prop_assert_eq!(normalized.parse::<StoragePath>().ok(), Some(path));
+                prop_assert!(!normalized.contains("//") && !normalized.contains('\\'));
+                prop_assert!(normalized == "/" || !normalized.ends_with('/'));
+                prop_assert!(normalized.split('/').all(|name| name != "." && name != ".."));
+            }
+        }
+
+        #[test]
+        fn redundant_spellings_resolve_to_the_same_path(
+            names in prop::collection::vec("[a-z]{1,8}", 1..6),
+            noise in prop::collection::vec(0..5usize, 6),
+        ) {
+            let separators = ["/", "//", "/./", "\\", "/detour/../"];
+            let noisy: String = names
+                .iter()
+                .zip(&noise)
+                .map(|(name, noise)| format!("{}{}", separators[*noise], name))
+                .collect();
+            let canonical = format!("/{}", names.join("/"));
+
+            prop_assert_eq!(
+                noisy.parse::<StoragePath>().ok().map(|path| path.to_string()),
+                Some(canonical)
+            );
+        }
+
+        #[test]
+        fn climbing_above_the_start_is_refused(
+            names in prop::collection::vec("[a-z]{1,8}", 0..5),
+            extra in 1..4usize,
+        ) {
+            let climb = vec![".."; names.len() + extra];
+            let raw = format!("/{}/{}", names.join("/"), climb.join("/"));
+
+            prop_assert!(matches!(
+                raw.parse::<StoragePath>(),
+                Err(StorageError::PathEscapesRoot(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,3 @@
+drop index if exists files_name_path;
+
+drop table if exists files;
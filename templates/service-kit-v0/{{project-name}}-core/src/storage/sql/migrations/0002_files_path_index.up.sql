@@ -0,0 +1 @@
+create index if not exists files_path_name on files (path, name);
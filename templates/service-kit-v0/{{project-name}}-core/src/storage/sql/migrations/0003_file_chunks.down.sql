@@ -0,0 +1,9 @@
+update files set contents = coalesce(
+  (
+    select cast(group_concat(data, '') as blob)
+    from (select data from file_chunks where file_id = files.id order by seq)
+  ),
+  x''
+);
+
+drop table if exists file_chunks;
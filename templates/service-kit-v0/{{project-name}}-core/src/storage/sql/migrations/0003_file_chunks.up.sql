@@ -0,0 +1,11 @@
+create table if not exists file_chunks (
+  file_id    integer    not null references files (id) on delete cascade,
+  seq        integer    not null,
+  data       blob       not null,
+  primary key (file_id, seq)
+);
+
+insert into file_chunks (file_id, seq, data)
+  select id, 0, contents from files where length(contents) > 0;
+
+update files set contents = x'';
@@ -0,0 +1,17 @@
+create table if not exists file_chunks (
+  file_id    integer    not null references files (id) on delete cascade,
+  seq        integer    not null,
+  data       blob       not null,
+  primary key (file_id, seq)
+);
+
+insert into file_chunks (file_id, seq, data)
+  select files.id, blob_chunks.seq, blob_chunks.data
+  from files
+  join blob_chunks on blob_chunks.hash = files.hash;
+
+alter table files drop column hash;
+
+drop table if exists blob_chunks;
+
+drop table if exists blobs;
@@ -0,0 +1,26 @@
+create table if not exists blobs (
+  hash       text       primary key,
+  size       integer    not null,
+  refcount   integer    not null default 0
+);
+
+create table if not exists blob_chunks (
+  hash       text       not null,
+  seq        integer    not null,
+  data       blob       not null,
+  primary key (hash, seq)
+);
+
+alter table files add column hash text;
+
+-- existing contents get a placeholder hash, which is swapped for the real
+-- digest the next time the collection is opened
+update files set hash = 'legacy:' || id;
+
+insert into blobs (hash, size, refcount)
+  select hash, size, 1 from files;
+
+insert into blob_chunks (hash, seq, data)
+  select 'legacy:' || file_id, seq, data from file_chunks;
+
+drop table if exists file_chunks;
@@ -0,0 +1,10 @@
+-- versions hold a reference to their contents; give them back before dropping
+update blobs set refcount = refcount - (
+  select count(*) from file_versions where file_versions.hash = blobs.hash
+);
+
+delete from blob_chunks where hash in (select hash from blobs where refcount <= 0);
+delete from blobs where refcount <= 0;
+
+drop index if exists file_versions_path_name_version;
+drop table if exists file_versions;
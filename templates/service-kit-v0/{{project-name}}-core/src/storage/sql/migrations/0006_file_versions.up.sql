@@ -0,0 +1,11 @@
+create table if not exists file_versions (
+  id          integer    primary key autoincrement,
+  path        text       not null,
+  name        text       not null,
+  version     integer    not null,
+  hash        text       not null,
+  size        integer    not null,
+  replaced_at timestamp  default current_timestamp
+);
+
+create unique index if not exists file_versions_path_name_version on file_versions (path, name, version);
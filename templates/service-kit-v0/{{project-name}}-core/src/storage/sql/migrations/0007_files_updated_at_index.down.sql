@@ -0,0 +1 @@
+drop index if exists files_updated_at;
@@ -0,0 +1 @@
+create index if not exists files_updated_at on files (updated_at);
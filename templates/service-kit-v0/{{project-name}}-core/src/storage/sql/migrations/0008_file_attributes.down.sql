@@ -0,0 +1,3 @@
+alter table files drop column tags;
+alter table files drop column metadata;
+alter table files drop column content_type;
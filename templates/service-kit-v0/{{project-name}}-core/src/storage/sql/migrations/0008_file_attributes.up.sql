@@ -0,0 +1,3 @@
+alter table files add column content_type text;
+alter table files add column metadata text not null default '{}';
+alter table files add column tags text not null default '[]';
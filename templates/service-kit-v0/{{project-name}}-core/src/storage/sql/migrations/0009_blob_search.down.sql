@@ -0,0 +1,2 @@
+alter table blobs drop column searchable;
+drop table if exists blob_text;
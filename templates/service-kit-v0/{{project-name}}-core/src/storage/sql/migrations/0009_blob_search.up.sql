@@ -0,0 +1,9 @@
+create virtual table if not exists blob_text using fts5 (
+  hash unindexed,
+  body,
+  tokenize = 'porter unicode61'
+);
+
+-- null until the blob has been looked at; existing blobs are indexed the
+-- next time the collection is opened
+alter table blobs add column searchable integer;
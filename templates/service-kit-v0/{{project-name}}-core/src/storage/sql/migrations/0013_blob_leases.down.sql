@@ -0,0 +1,8 @@
+update blobs
+set refcount = refcount - (select count(*) from blob_leases where blob_leases.hash = blobs.hash);
+
+delete from blob_chunks where hash in (select hash from blobs where refcount <= 0);
+delete from blob_text where hash in (select hash from blobs where refcount <= 0);
+delete from blobs where refcount <= 0;
+
+drop table if exists blob_leases;
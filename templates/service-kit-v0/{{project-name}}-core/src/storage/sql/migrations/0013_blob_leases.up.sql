@@ -0,0 +1,9 @@
+-- references held by readers, so that contents being streamed aren't dropped
+-- part way through when the files using them are overwritten or removed
+create table if not exists blob_leases (
+  id         integer    primary key autoincrement,
+  hash       text       not null,
+  expires_at timestamp  not null
+);
+
+create index if not exists blob_leases_expires_at on blob_leases (expires_at);
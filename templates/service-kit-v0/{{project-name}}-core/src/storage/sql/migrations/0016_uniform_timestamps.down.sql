@@ -0,0 +1,2 @@
+-- timestamps written to the millisecond still read back the same, so there's
+-- nothing to undo
@@ -0,0 +1,9 @@
+-- column defaults wrote timestamps to the second; every timestamp is now
+-- written to the millisecond, so they compare correctly as text
+update files set
+  created_at = strftime('%Y-%m-%d %H:%M:%f', created_at),
+  updated_at = strftime('%Y-%m-%d %H:%M:%f', updated_at);
+
+update file_versions set replaced_at = strftime('%Y-%m-%d %H:%M:%f', replaced_at);
+
+update schema_migrations set applied_at = strftime('%Y-%m-%d %H:%M:%f', applied_at);
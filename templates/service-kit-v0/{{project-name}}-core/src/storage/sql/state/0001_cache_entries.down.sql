@@ -0,0 +1,2 @@
+drop index if exists cache_entries_expires_at;
+drop table if exists cache_entries;
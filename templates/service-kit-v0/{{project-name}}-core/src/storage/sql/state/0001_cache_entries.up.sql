@@ -0,0 +1,9 @@
+create table if not exists cache_entries (
+  namespace  text       not null,
+  key        text       not null,
+  value      text       not null,
+  expires_at timestamp,
+  primary key (namespace, key)
+);
+
+create index if not exists cache_entries_expires_at on cache_entries (expires_at);
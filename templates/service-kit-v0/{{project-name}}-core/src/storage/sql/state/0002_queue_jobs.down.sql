@@ -0,0 +1,4 @@
+drop index if exists queue_dead_letters_queue;
+drop table if exists queue_dead_letters;
+drop index if exists queue_jobs_queue_visible_at;
+drop table if exists queue_jobs;
@@ -0,0 +1,28 @@
+create table if not exists queue_jobs (
+  id           integer    primary key autoincrement,
+  queue        text       not null,
+  payload      text       not null,
+  -- how many times the job has been handed out, which also identifies the
+  -- latest claim on it
+  attempts     integer    not null default 0,
+  max_attempts integer    not null,
+  -- when the job can next be handed out: once it's due, and again once a
+  -- claim on it has timed out
+  visible_at   timestamp  not null,
+  last_error   text,
+  created_at   timestamp  default current_timestamp
+);
+
+create index if not exists queue_jobs_queue_visible_at on queue_jobs (queue, visible_at);
+
+create table if not exists queue_dead_letters (
+  id         integer    primary key autoincrement,
+  queue      text       not null,
+  payload    text       not null,
+  attempts   integer    not null,
+  last_error text,
+  created_at timestamp  not null,
+  failed_at  timestamp  default current_timestamp
+);
+
+create index if not exists queue_dead_letters_queue on queue_dead_letters (queue);
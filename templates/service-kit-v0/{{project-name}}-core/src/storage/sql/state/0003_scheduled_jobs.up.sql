@@ -0,0 +1,13 @@
+create table if not exists scheduled_jobs (
+  name             text       primary key not null,
+  schedule         text       not null,
+  next_run_at      timestamp  not null,
+  -- the instance running the job, which holds it until `locked_until`
+  locked_by        text,
+  locked_until     timestamp,
+  last_started_at  timestamp,
+  last_finished_at timestamp,
+  last_error       text,
+  runs             integer    not null default 0,
+  failures         integer    not null default 0
+);
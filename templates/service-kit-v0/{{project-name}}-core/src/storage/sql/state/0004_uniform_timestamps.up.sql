@@ -0,0 +1,7 @@
+-- column defaults wrote timestamps to the second; every timestamp is now
+-- written to the millisecond, so they compare correctly as text
+update queue_jobs set created_at = strftime('%Y-%m-%d %H:%M:%f', created_at);
+
+update queue_dead_letters set
+  created_at = strftime('%Y-%m-%d %H:%M:%f', created_at),
+  failed_at = strftime('%Y-%m-%d %H:%M:%f', failed_at);
@@ -0,0 +1 @@
+drop table if exists storage_seeds;
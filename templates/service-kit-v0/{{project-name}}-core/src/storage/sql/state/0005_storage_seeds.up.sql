@@ -0,0 +1,8 @@
+-- the paths seeded into storage, whichever backend it is; databases shared
+-- with the sqlite backend already have this table from its own migrations
+create table if not exists storage_seeds (
+  path       text       primary key not null,
+  applied_at timestamp  not null
+);
+
+update storage_seeds set applied_at = strftime('%Y-%m-%d %H:%M:%f', applied_at);
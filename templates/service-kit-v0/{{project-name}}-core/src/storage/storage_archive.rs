@@ -0,0 +1,346 @@
+//! Portable copies of a collection's files. An archive is a zip file holding
+//! each file's contents at its storage path below `files/`, and a
+//! `manifest.json` listing every file's metadata. The manifest is what gets
+//! imported; entries it doesn't mention are ignored. Kept versions are left
+//! out.
+
+use std::io::{Read, Seek, Write};
+
+use bytes::Bytes;
+use chrono::{Datelike, Timelike};
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use super::{
+    storage_file_stream::CHUNK_SIZE, StorageBackend, StorageError, StorageFileMeta, StoragePath,
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Bumped whenever the layout changes in a way older builds can't read.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    format: u32,
+    exported_at: chrono::DateTime<chrono::Utc>,
+    files: Vec<StorageFileMeta>,
+}
+
+/// What to do with an imported file whose path is already taken.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageImportConflict {
+    /// Abandon the whole import.
+    #[default]
+    Fail,
+    /// Keep the stored file and leave the archived one out.
+    Skip,
+    /// Replace the stored file, keeping it as a version if history is on.
+    Overwrite,
+}
+
+/// The paths an import stored, and those it left alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct StorageImportSummary {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Where the contents of the file at `path` live in an archive.
+fn entry_name(path: &StoragePath) -> String {
+    format!("files{}", path)
+}
+
+/// Where an archived file goes, checked the same way as any path from outside.
+pub fn archived_path(meta: &StorageFileMeta) -> crate::Result<StoragePath> {
+    let path: StoragePath = format!("{}/{}", meta.path, meta.name).parse()?;
+
+    path.expect_absolute()?;
+
+    Ok(path)
+}
+
+/// Zip timestamps can't go below 1980, so earlier times are left unset.
+fn zip_time(time: chrono::DateTime<chrono::Utc>) -> Option<zip::DateTime> {
+    zip::DateTime::from_date_and_time(
+        u16::try_from(time.year()).ok()?,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .ok()
+}
+
+/// Writes an archive a file at a time, then the manifest once they're all in.
+pub struct ArchiveWriter<W: Write + Seek> {
+    zip: zip::ZipWriter<W>,
+    files: Vec<StorageFileMeta>,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            zip: zip::ZipWriter::new(writer),
+            files: vec![],
+        }
+    }
+
+    /// Begin the entry for `meta`; its contents follow through `write`.
+    pub fn start(&mut self, meta: &StorageFileMeta) -> crate::Result<()> {
+        let mut options =
+            zip::write::SimpleFileOptions::default().large_file(meta.size > u32::MAX as i64);
+
+        if let Some(time) = zip_time(meta.updated_at) {
+            options = options.last_modified_time(time);
+        }
+
+        self.zip
+            .start_file(entry_name(&meta.full_path()), options)?;
+        self.files.push(meta.clone());
+
+        Ok(())
+    }
+
+    pub fn write(&mut self, contents: &[u8]) -> crate::Result<()> {
+        self.zip.write_all(contents)?;
+
+        Ok(())
+    }
+
+    /// Write the manifest and close the archive, returning what it holds.
+    pub fn finish(mut self) -> crate::Result<Vec<StorageFileMeta>> {
+        let manifest = Manifest {
+            format: FORMAT_VERSION,
+            exported_at: chrono::Utc::now(),
+            files: self.files,
+        };
+
+        self.zip
+            .start_file(MANIFEST_NAME, zip::write::SimpleFileOptions::default())?;
+        serde_json::to_writer_pretty(&mut self.zip, &manifest)?;
+        self.zip.finish()?;
+
+        Ok(manifest.files)
+    }
+}
+
+/// Reads back an archive written by `ArchiveWriter`.
+pub struct ArchiveReader<R: Read + Seek> {
+    zip: zip::ZipArchive<R>,
+    pub files: Vec<StorageFileMeta>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    pub fn open(reader: R) -> crate::Result<Self> {
+        let mut zip = zip::ZipArchive::new(reader)?;
+        let manifest: Manifest = match zip.by_name(MANIFEST_NAME) {
+            Ok(entry) => serde_json::from_reader(entry)?,
+            Err(zip::result::ZipError::FileNotFound) => {
+                return Err(StorageError::InvalidArchive(format!(
+                    "no {} found",
+                    MANIFEST_NAME
+                )))?
+            }
+            Err(error) => return Err(error)?,
+        };
+
+        if manifest.format != FORMAT_VERSION {
+            return Err(StorageError::InvalidArchive(format!(
+                "unsupported format {}",
+                manifest.format
+            )))?;
+        }
+
+        Ok(Self {
+            zip,
+            files: manifest.files,
+        })
+    }
+
+    /// The archived contents of `meta`, read no further than one byte past
+    /// the size the manifest gives, whatever the entry itself claims.
+    fn entry(&mut self, meta: &StorageFileMeta) -> crate::Result<impl Read + '_> {
+        let path = archived_path(meta)?;
+
+        match self.zip.by_name(&entry_name(&path)) {
+            Ok(entry) => Ok(entry.take(meta.size.max(0) as u64 + 1)),
+            Err(zip::result::ZipError::FileNotFound) => Err(StorageError::InvalidArchive(
+                format!("no contents for {}", path),
+            ))?,
+            Err(error) => Err(error)?,
+        }
+    }
+
+    /// Check every file's contents against the size and hash in the manifest.
+    pub fn verify(&mut self) -> crate::Result<()> {
+        for meta in self.files.clone() {
+            let mut hasher = Sha256::new();
+            let size = std::io::copy(&mut self.entry(&meta)?, &mut hasher)?;
+
+            if size != meta.size as u64 || format!("{:x}", hasher.finalize()) != meta.hash {
+                return Err(StorageError::ArchiveMismatch(meta.full_path().to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> ArchiveReader<R> {
+    /// Read the contents of each of `files` on a blocking thread, handing
+    /// them over a chunk at a time through one channel per file, in order.
+    fn stream(
+        mut self,
+        files: Vec<StorageFileMeta>,
+    ) -> mpsc::Receiver<mpsc::Receiver<std::io::Result<Bytes>>> {
+        let (sender, entries) = mpsc::channel(1);
+
+        tokio::task::spawn_blocking(move || {
+            let mut buffer = vec![0; CHUNK_SIZE];
+
+            for meta in files {
+                let (chunks, receiver) = mpsc::channel(1);
+
+                // the import has been abandoned
+                if sender.blocking_send(receiver).is_err() {
+                    return;
+                }
+
+                let mut entry = match self.entry(&meta) {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        let _ = chunks.blocking_send(Err(std::io::Error::other(error)));
+
+                        return;
+                    }
+                };
+
+                loop {
+                    let chunk = match entry.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(read) => Ok(Bytes::copy_from_slice(&buffer[..read])),
+                        Err(error) => Err(error),
+                    };
+                    let failed = chunk.is_err();
+
+                    if chunks.blocking_send(chunk).is_err() || failed {
+                        return;
+                    }
+                }
+            }
+        });
+
+        entries
+    }
+}
+
+/// Write every file in `storage`, with its metadata, to a zip archive that
+/// `import` can read back. Each file is copied as it was when it was opened,
+/// so writes made meanwhile can't tear it, and files removed since they were
+/// listed are left out. Returns the files written.
+pub async fn export(
+    storage: &dyn StorageBackend,
+    writer: impl Write + Seek,
+) -> crate::Result<Vec<StorageFileMeta>> {
+    let mut archive = ArchiveWriter::new(writer);
+
+    for meta in storage.walk(StoragePath::new("/".into())).await? {
+        let mut stream = match storage.open(meta.full_path()).await {
+            Ok(stream) => stream,
+            Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => continue,
+            Err(error) => return Err(error),
+        };
+
+        archive.start(&stream.meta)?;
+
+        while let Some(chunk) = stream.next().await {
+            archive.write(&chunk?)?;
+        }
+    }
+
+    archive.finish()
+}
+
+/// Store every file in an archive written by `export` in `storage`, with its
+/// attributes, and its timestamps where the backend can keep them. Files
+/// whose path is already taken are handled according to `conflict`. The
+/// whole archive is checked before anything is stored, but a write failing
+/// part way through leaves the files before it imported.
+pub async fn import(
+    storage: &dyn StorageBackend,
+    reader: impl Read + Seek + Send + 'static,
+    conflict: StorageImportConflict,
+) -> crate::Result<StorageImportSummary> {
+    let mut archive = tokio::task::spawn_blocking(move || {
+        let mut archive = ArchiveReader::open(reader)?;
+
+        archive.verify()?;
+
+        crate::Result::Ok(archive)
+    })
+    .await
+    .map_err(std::io::Error::from)??;
+    let mut summary = StorageImportSummary::default();
+    let mut wanted = vec![];
+
+    for meta in std::mem::take(&mut archive.files) {
+        let path = archived_path(&meta)?;
+
+        match storage.stat(path.clone()).await {
+            Ok(_) => match conflict {
+                StorageImportConflict::Fail => {
+                    return Err(StorageError::FileExists(path.to_string()))?
+                }
+                StorageImportConflict::Skip => {
+                    summary.skipped.push(path.to_string());
+                    continue;
+                }
+                StorageImportConflict::Overwrite => {}
+            },
+            Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => {}
+            Err(error) => return Err(error),
+        }
+
+        wanted.push((path, meta));
+    }
+
+    let mut entries = archive.stream(wanted.iter().map(|(_, meta)| meta.clone()).collect());
+
+    for (path, meta) in wanted {
+        let chunks = entries
+            .recv()
+            .await
+            .ok_or_else(|| StorageError::InvalidArchive(format!("no contents for {}", path)))?;
+        let mut contents = tokio_util::io::StreamReader::new(
+            futures::stream::unfold(chunks, |mut chunks| async move {
+                chunks.recv().await.map(|chunk| (chunk, chunks))
+            })
+            .boxed(),
+        );
+
+        storage
+            .put_with(path.clone(), &mut contents, meta.attributes.clone())
+            .await?;
+        storage
+            .set_times(path.clone(), meta.created_at, meta.updated_at)
+            .await?;
+
+        summary.imported.push(path.to_string());
+    }
+
+    Ok(summary)
+}
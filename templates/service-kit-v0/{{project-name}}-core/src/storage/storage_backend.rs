@@ -0,0 +1,481 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::stream::BoxStream;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{
+    StorageBatch, StorageBatchContents, StorageBatchOperation, StorageCollection, StorageEntry,
+    StorageError, StorageEvent, StorageFile, StorageFileAttributes, StorageFileMeta,
+    StorageFileStream, StorageFileVersion, StoragePage, StoragePath, StorageSearchHit,
+    StorageSyncAction, StorageSyncOptions, StorageSyncPlan,
+};
+
+/// A shared handle to whichever backend the configuration selected.
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// Apply one batch operation using only the basic backend operations.
+async fn apply_operation<B: StorageBackend + ?Sized>(
+    backend: &B,
+    operation: StorageBatchOperation,
+) -> crate::Result<()> {
+    match operation {
+        StorageBatchOperation::Insert { path, contents } => match contents {
+            StorageBatchContents::Bytes(contents) => backend.put(path, contents).await,
+            contents => {
+                backend
+                    .put_stream(path, &mut contents.reader().await?)
+                    .await
+            }
+        },
+        StorageBatchOperation::Remove { path } => backend.delete(path).await,
+        StorageBatchOperation::Rename {
+            from,
+            to,
+            overwrite,
+        } => backend.rename(from, to, overwrite).await,
+    }
+}
+
+/// Store `file`'s contents and attributes at `path`.
+async fn put_file<B: StorageBackend + ?Sized>(
+    backend: &B,
+    path: StoragePath,
+    file: StorageFile,
+) -> crate::Result<()> {
+    backend
+        .put_with(path, &mut file.contents.as_slice(), file.attributes)
+        .await?;
+
+    Ok(())
+}
+
+/// Refuse to write to `path` if it already holds a file, unless `overwrite` is set.
+async fn expect_vacant<B: StorageBackend + ?Sized>(
+    backend: &B,
+    path: &StoragePath,
+    overwrite: bool,
+) -> crate::Result<()> {
+    if overwrite {
+        return Ok(());
+    }
+
+    match backend.stat(path.clone()).await {
+        Ok(_) => Err(StorageError::FileExists(path.to_string()))?,
+        Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Somewhere files can be stored. Backends only need to provide the basic
+/// operations; everything else has a default built on top of them, which
+/// backends can override with something more efficient.
+#[async_trait::async_trait]
+pub trait StorageBackend: std::fmt::Debug + Send + Sync {
+    async fn get(&self, path: StoragePath) -> crate::Result<StorageFile>;
+
+    /// Store `contents` at `path`, replacing any file already there.
+    async fn put(&self, path: StoragePath, contents: Vec<u8>) -> crate::Result<()>;
+
+    async fn delete(&self, path: StoragePath) -> crate::Result<()>;
+
+    /// The immediate children of a directory, ordered by name with
+    /// directories sorting as if their names ended in a separator.
+    async fn list(&self, dir: StoragePath) -> crate::Result<Vec<StorageEntry>>;
+
+    async fn stat(&self, path: StoragePath) -> crate::Result<StorageFileMeta>;
+
+    /// Store everything `reader` produces at `path`.
+    async fn put_stream(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> crate::Result<()> {
+        let mut contents = vec![];
+
+        reader.read_to_end(&mut contents).await?;
+
+        self.put(path, contents).await
+    }
+
+    /// Store everything `reader` produces at `path`, and give the file
+    /// `attributes`. Returns whether the file is new, rather than replacing
+    /// one. By default that's checked before the contents are written, and
+    /// the attributes are set after, if there are any; backends that can
+    /// tell as they write override this.
+    async fn put_with(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        let created = match self.stat(path.clone()).await {
+            Ok(_) => false,
+            Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => true,
+            Err(error) => return Err(error),
+        };
+
+        self.put_stream(path.clone(), reader).await?;
+
+        if !attributes.is_empty() {
+            self.set_attributes(path, attributes).await?;
+        }
+
+        Ok(created)
+    }
+
+    /// Replace the attributes of the file at `path`.
+    async fn set_attributes(
+        &self,
+        _path: StoragePath,
+        _attributes: StorageFileAttributes,
+    ) -> crate::Result<()> {
+        Err(StorageError::AttributesUnsupported)?
+    }
+
+    /// Give the file at `path` the times it was created and last written,
+    /// such as when it's restored from an archive. Backends that keep their
+    /// own times leave them as they are.
+    async fn set_times(
+        &self,
+        _path: StoragePath,
+        _created_at: chrono::DateTime<chrono::Utc>,
+        _updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Every file carrying `tag`, ordered by directory then name.
+    async fn find_by_tag(&self, tag: &str) -> crate::Result<Vec<StorageFileMeta>> {
+        let files = self.walk(StoragePath::new("/".into())).await?;
+
+        Ok(files
+            .into_iter()
+            .filter(|meta| meta.attributes.tags.contains(tag))
+            .collect())
+    }
+
+    /// The files whose contents contain every word of `query`, best matches
+    /// first, for backends that keep a search index.
+    async fn search(&self, _query: &str, _limit: usize) -> crate::Result<Vec<StorageSearchHit>> {
+        Err(StorageError::SearchUnsupported)?
+    }
+
+    /// Changes to files at or below `prefix` from now on, for backends that
+    /// announce them. The stream ends if the watcher falls too far behind.
+    fn watch(&self, _prefix: StoragePath) -> crate::Result<BoxStream<'static, StorageEvent>> {
+        Err(StorageError::WatchUnsupported)?
+    }
+
+    /// Release whatever the backend holds open, such as database
+    /// connections. It can't be used afterwards.
+    async fn close(&self) {}
+
+    /// The SQLite collection behind this backend, if that's what it is, for
+    /// the maintenance only it supports.
+    fn as_collection(&self) -> Option<&StorageCollection> {
+        None
+    }
+
+    /// Open a file for streaming.
+    async fn open(&self, path: StoragePath) -> crate::Result<StorageFileStream> {
+        Ok(StorageFileStream::from_file(self.get(path).await?))
+    }
+
+    /// Every file stored anywhere below `prefix`, ordered by directory then name.
+    async fn walk(&self, prefix: StoragePath) -> crate::Result<Vec<StorageFileMeta>> {
+        let mut files = vec![];
+        let mut dirs = vec![prefix];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in self.list(dir).await? {
+                match entry {
+                    StorageEntry::File(meta) => files.push(meta),
+                    StorageEntry::Directory { path, .. } => dirs.push(path.parse()?),
+                }
+            }
+        }
+
+        files.sort_by(|a, b| (&a.path, &a.name).cmp(&(&b.path, &b.name)));
+
+        Ok(files)
+    }
+
+    /// Like `list`, but returns at most `limit` entries, starting after `cursor`.
+    async fn list_page(
+        &self,
+        dir: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageEntry>> {
+        let mut entries: Vec<_> = self
+            .list(dir)
+            .await?
+            .into_iter()
+            .filter(|entry| cursor.as_ref().is_none_or(|c| entry.cursor_key() > *c))
+            .collect();
+
+        entries.truncate(limit.saturating_add(1));
+
+        Ok(StoragePage::paginate(entries, limit, |entry| {
+            entry.cursor_key()
+        }))
+    }
+
+    /// Like `walk`, but returns at most `limit` files, starting after `cursor`.
+    async fn walk_page(
+        &self,
+        prefix: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageFileMeta>> {
+        let after = match cursor {
+            Some(cursor) => {
+                let cursor: StoragePath = cursor.parse()?;
+
+                Some((cursor.parent()?.directory(), cursor.file_name()?))
+            }
+            None => None,
+        };
+
+        let mut files: Vec<_> = self
+            .walk(prefix)
+            .await?
+            .into_iter()
+            .filter(|meta| {
+                after
+                    .as_ref()
+                    .is_none_or(|(path, name)| (&meta.path, &meta.name) > (path, name))
+            })
+            .collect();
+
+        files.truncate(limit.saturating_add(1));
+
+        Ok(StoragePage::paginate(files, limit, |meta| {
+            meta.full_path().to_string()
+        }))
+    }
+
+    /// Every file whose contents were written after `since`, oldest change
+    /// first.
+    async fn modified_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<Vec<StorageFileMeta>> {
+        let mut files: Vec<_> = self
+            .walk(StoragePath::new("/".into()))
+            .await?
+            .into_iter()
+            .filter(|meta| meta.updated_at > since)
+            .collect();
+
+        files.sort_by(|a, b| {
+            (a.updated_at, &a.path, &a.name).cmp(&(b.updated_at, &b.path, &b.name))
+        });
+
+        Ok(files)
+    }
+
+    /// Move the file at `from` to `to`. If `to` already holds a file, this
+    /// fails with `FileExists` unless `overwrite` is set. By default the file
+    /// is copied and then deleted, which isn't atomic: a failure or a
+    /// concurrent write in between can leave it at both paths, and the check
+    /// for an existing file at `to` can race with another writer.
+    async fn rename(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        let file = self.get(from.clone()).await?;
+
+        if from.to_string() == to.to_string() {
+            return Ok(());
+        }
+
+        expect_vacant(self, &to, overwrite).await?;
+
+        put_file(self, to, file).await?;
+        self.delete(from).await
+    }
+
+    /// Store a copy of the file at `from` at `to`, with the same overwrite
+    /// rules as `rename`.
+    async fn copy(&self, from: StoragePath, to: StoragePath, overwrite: bool) -> crate::Result<()> {
+        let file = self.get(from).await?;
+
+        expect_vacant(self, &to, overwrite).await?;
+
+        put_file(self, to, file).await
+    }
+
+    /// Move every file below the directory `from` to the same place below
+    /// `to`, all or nothing as far as `apply_batch` is. Without `overwrite`,
+    /// the move fails up front if any of them would replace a file that isn't
+    /// itself being moved.
+    async fn move_directory(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        if to.is_within(&from) {
+            return Err(StorageError::MoveIntoSelf {
+                from: from.to_string(),
+                to: to.to_string(),
+            })?;
+        }
+
+        let sources: Vec<_> = self
+            .walk(from.clone())
+            .await?
+            .iter()
+            .map(|meta| meta.full_path())
+            .collect();
+
+        if sources.is_empty() {
+            return Err(StorageError::DirectoryNotFound(from.to_string()))?;
+        }
+
+        let mut batch = StorageBatch::new();
+
+        for source in &sources {
+            let target = source
+                .rebase(&from, &to)
+                .ok_or_else(|| StorageError::BadPath(source.to_string().into()))?;
+
+            if !sources.iter().any(|s| s.to_string() == target.to_string()) {
+                expect_vacant(self, &target, overwrite).await?;
+            }
+
+            // vacancy was checked above, and a target that's moved itself is fine
+            batch = batch.rename(source.clone(), target, true);
+        }
+
+        self.apply_batch(batch).await
+    }
+
+    /// Apply every operation in `batch`, or none of them. By default the
+    /// previous file at every path the batch touches is kept aside and put
+    /// back if an operation fails; backends with real transactions override
+    /// this. That isn't atomic: other readers see the batch part way through,
+    /// concurrent writes to the same paths can be lost when it's put back,
+    /// and a crash or a failure while putting it back leaves it half applied.
+    #[tracing::instrument(level = "debug", skip(self, batch))]
+    async fn apply_batch(&self, batch: StorageBatch) -> crate::Result<()> {
+        let mut previous = vec![];
+
+        for path in batch.paths() {
+            let file = match self.get(path.clone()).await {
+                Ok(file) => Some(file),
+                Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => None,
+                Err(error) => return Err(error),
+            };
+
+            previous.push((path, file));
+        }
+
+        for operation in batch.operations {
+            let Err(error) = apply_operation(self, operation).await else {
+                continue;
+            };
+
+            tracing::warn!(
+                "Storage batch failed, restoring previous contents: {}",
+                error
+            );
+
+            for (path, file) in previous {
+                let restored = match file {
+                    Some(file) => put_file(self, path.clone(), file).await,
+                    None => match self.delete(path.clone()).await {
+                        Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => Ok(()),
+                        result => result,
+                    },
+                };
+
+                if let Err(restore_error) = restored {
+                    tracing::error!("Unable to restore {}: {}", path, restore_error);
+                }
+            }
+
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// The kept versions of the file at `path`, newest first. Backends that
+    /// don't keep history never have any.
+    async fn versions(&self, _path: StoragePath) -> crate::Result<Vec<StorageFileVersion>> {
+        Ok(vec![])
+    }
+
+    /// Open a previous version of a file for streaming.
+    async fn open_version(
+        &self,
+        path: StoragePath,
+        version: i64,
+    ) -> crate::Result<StorageFileStream> {
+        Err(StorageError::VersionNotFound {
+            path: path.to_string(),
+            version,
+        })?
+    }
+
+    async fn get_version(&self, path: StoragePath, version: i64) -> crate::Result<StorageFile> {
+        self.open_version(path, version).await?.read_all().await
+    }
+
+    /// Make a previous version the current contents of `path`.
+    async fn restore(&self, path: StoragePath, version: i64) -> crate::Result<()> {
+        Err(StorageError::VersionNotFound {
+            path: path.to_string(),
+            version,
+        })?
+    }
+
+    /// Whether contents with the given SHA-256 hash are already stored.
+    /// Backends that don't share contents between files never have any.
+    async fn contains(&self, _hash: &str) -> crate::Result<bool> {
+        Ok(false)
+    }
+
+    /// Store a file at `path` whose contents are the already stored blob `hash`.
+    /// Returns whether the file is new, rather than replacing one.
+    async fn link(&self, _path: StoragePath, hash: &str) -> crate::Result<bool> {
+        Err(StorageError::UnknownContent(hash.to_string()))?
+    }
+
+    /// Work out what `apply_sync` would need to do to make the files under
+    /// `prefix` match the local directory `local`.
+    async fn sync_plan(
+        &self,
+        local: &Path,
+        prefix: StoragePath,
+        options: &StorageSyncOptions,
+    ) -> crate::Result<StorageSyncPlan> {
+        let remote = self.walk(prefix.clone()).await?;
+
+        StorageSyncPlan::build(local, &prefix, remote, options).await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, plan))]
+    async fn apply_sync(&self, plan: &StorageSyncPlan) -> crate::Result<()> {
+        for action in &plan.actions {
+            tracing::debug!("Sync: {}", action);
+
+            match action {
+                StorageSyncAction::Create { local, remote }
+                | StorageSyncAction::Update { local, remote } => {
+                    let mut file = tokio::fs::File::open(local).await?;
+
+                    self.put_stream(remote.parse()?, &mut file).await?
+                }
+                StorageSyncAction::Delete { remote } => self.delete(remote.parse()?).await?,
+            }
+        }
+
+        Ok(())
+    }
+}
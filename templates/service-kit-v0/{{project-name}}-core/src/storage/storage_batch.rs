@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use tokio::io::AsyncRead;
+
+use super::StoragePath;
+
+/// A set of changes to apply together: either every operation succeeds, or
+/// none of them are kept.
+#[derive(Clone, Debug, Default)]
+pub struct StorageBatch {
+    pub operations: Vec<StorageBatchOperation>,
+}
+
+#[derive(Clone, Debug)]
+pub enum StorageBatchOperation {
+    /// Store `contents` at `path`, replacing any file already there.
+    Insert {
+        path: StoragePath,
+        contents: StorageBatchContents,
+    },
+    Remove {
+        path: StoragePath,
+    },
+    /// Move the file at `from` to `to`. A file already at `to` is only
+    /// replaced with `overwrite`; otherwise the batch fails.
+    Rename {
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    },
+}
+
+/// Where an inserted file's contents come from.
+#[derive(Clone, Debug)]
+pub enum StorageBatchContents {
+    Bytes(Vec<u8>),
+    /// A local file, read when the batch is applied, such as a request part
+    /// spooled to disk so it doesn't have to be held in memory.
+    File(PathBuf),
+}
+
+impl StorageBatchContents {
+    pub async fn reader(&self) -> crate::Result<Box<dyn AsyncRead + Unpin + Send + '_>> {
+        Ok(match self {
+            Self::Bytes(contents) => Box::new(contents.as_slice()),
+            Self::File(path) => Box::new(tokio::fs::File::open(path).await?),
+        })
+    }
+}
+
+impl StorageBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(mut self, path: StoragePath, contents: Vec<u8>) -> Self {
+        self.operations.push(StorageBatchOperation::Insert {
+            path,
+            contents: StorageBatchContents::Bytes(contents),
+        });
+        self
+    }
+
+    /// Store the contents of the local file at `file` at `path`. The file
+    /// has to stay in place until the batch is applied.
+    pub fn insert_file(mut self, path: StoragePath, file: PathBuf) -> Self {
+        self.operations.push(StorageBatchOperation::Insert {
+            path,
+            contents: StorageBatchContents::File(file),
+        });
+        self
+    }
+
+    pub fn remove(mut self, path: StoragePath) -> Self {
+        self.operations.push(StorageBatchOperation::Remove { path });
+        self
+    }
+
+    pub fn rename(mut self, from: StoragePath, to: StoragePath, overwrite: bool) -> Self {
+        self.operations.push(StorageBatchOperation::Rename {
+            from,
+            to,
+            overwrite,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Every path the batch could change, in the order they're first touched.
+    pub fn paths(&self) -> Vec<StoragePath> {
+        let mut paths: Vec<StoragePath> = vec![];
+
+        for operation in &self.operations {
+            let touched = match operation {
+                StorageBatchOperation::Insert { path, .. }
+                | StorageBatchOperation::Remove { path } => vec![path],
+                StorageBatchOperation::Rename { from, to, .. } => vec![from, to],
+            };
+
+            for path in touched {
+                if !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+
+        paths
+    }
+}
@@ -0,0 +1,506 @@
+//! Content-addressed blobs. File contents live in `blob_chunks` keyed by the
+//! SHA-256 of the whole blob, and each `files` row points at one blob by hash.
+//! `blobs.refcount` tracks how many files share a blob, so identical contents
+//! are stored once and dropped when the last file using them goes away.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{sql_timestamp, storage_file_stream::CHUNK_SIZE, storage_search, StorageError};
+
+/// Chunks are written under a key starting with this while their hash is
+/// still unknown. Each write adds the time it started and a random suffix.
+const STAGING: &str = "staging";
+
+/// How long staged chunks are kept before `discard_abandoned` drops them as
+/// left behind by a write that never finished.
+const STAGING_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Prefix of the placeholder hashes given to contents stored before blobs
+/// were content-addressed.
+const LEGACY: &str = "legacy:";
+
+/// How long a lease lasts unless it's renewed. Readers renew theirs long
+/// before then, so only leases left by a process that stopped mid-read run out.
+const LEASE_DURATION: Duration = Duration::from_secs(10 * 60);
+
+fn hex_digest(hasher: Sha256) -> String {
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash a local file the same way stored contents are hashed.
+pub async fn file_hash(path: &Path) -> crate::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        match file.read(&mut buffer).await? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+
+    Ok(hex_digest(hasher))
+}
+
+pub async fn exists(conn: &mut SqliteConnection, hash: &str) -> crate::Result<bool> {
+    let found: Option<(i64,)> = sqlx::query_as("select 1 from blobs where hash = $1")
+        .bind(hash)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(found.is_some())
+}
+
+/// Contents read by `stage`, waiting under a staging key to be committed as
+/// a blob or discarded.
+pub struct StagedBlob {
+    key: String,
+    pub hash: String,
+    pub size: i64,
+    // kept for the search index, unless they're too large to be indexed
+    contents: Option<Vec<u8>>,
+}
+
+/// Read `reader` into staged chunks. Each chunk is written on its own, so no
+/// transaction is held open while a slow reader is waited on. If reading
+/// fails, whatever was staged is discarded.
+pub async fn stage(
+    pool: &sqlx::SqlitePool,
+    reader: &mut (impl AsyncRead + Unpin),
+) -> crate::Result<StagedBlob> {
+    let mut staged = StagedBlob {
+        key: format!(
+            "{}:{}:{:016x}",
+            STAGING,
+            sql_timestamp(chrono::Utc::now()),
+            rand::random::<u64>()
+        ),
+        hash: String::new(),
+        size: 0,
+        contents: Some(vec![]),
+    };
+
+    match staged.fill(pool, reader).await {
+        Ok(hash) => {
+            staged.hash = hash;
+
+            Ok(staged)
+        }
+        Err(error) => {
+            staged.discard(pool).await?;
+
+            Err(error)
+        }
+    }
+}
+
+impl StagedBlob {
+    async fn fill(
+        &mut self,
+        pool: &sqlx::SqlitePool,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> crate::Result<String> {
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut seq = 0;
+
+        loop {
+            let filled = fill_chunk(reader, &mut buffer).await?;
+
+            if filled == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..filled]);
+
+            self.contents = self
+                .contents
+                .take()
+                .filter(|kept| kept.len() + filled <= storage_search::MAX_INDEXED_SIZE)
+                .map(|mut kept| {
+                    kept.extend_from_slice(&buffer[..filled]);
+                    kept
+                });
+
+            sqlx::query("insert into blob_chunks (hash, seq, data) values ($1, $2, $3)")
+                .bind(&self.key)
+                .bind(seq)
+                .bind(&buffer[..filled])
+                .execute(pool)
+                .await?;
+
+            seq += 1;
+            self.size += filled as i64;
+
+            if filled < CHUNK_SIZE {
+                break;
+            }
+        }
+
+        Ok(hex_digest(hasher))
+    }
+
+    /// Turn the staged chunks into the blob `hash`, as part of whatever
+    /// transaction `conn` is in. If that blob already exists, they're dropped.
+    pub async fn commit(&mut self, conn: &mut SqliteConnection) -> crate::Result<()> {
+        if exists(conn, &self.hash).await? {
+            sqlx::query("delete from blob_chunks where hash = $1")
+                .bind(&self.key)
+                .execute(&mut *conn)
+                .await?;
+        } else {
+            sqlx::query("update blob_chunks set hash = $1 where hash = $2")
+                .bind(&self.hash)
+                .bind(&self.key)
+                .execute(&mut *conn)
+                .await?;
+
+            sqlx::query("insert into blobs (hash, size, refcount) values ($1, $2, 0)")
+                .bind(&self.hash)
+                .bind(self.size)
+                .execute(&mut *conn)
+                .await?;
+
+            storage_search::index(conn, &self.hash, self.contents.take()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop the staged chunks, for a write that failed before they were
+    /// committed. Once they have been, there's nothing left to drop.
+    pub async fn discard(&self, pool: &sqlx::SqlitePool) -> crate::Result<()> {
+        sqlx::query("delete from blob_chunks where hash = $1")
+            .bind(&self.key)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Drop chunks staged so long ago that the write staging them must have
+/// stopped without committing or discarding them.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn discard_abandoned(pool: &sqlx::SqlitePool) -> crate::Result<()> {
+    let cutoff = sql_timestamp(chrono::Utc::now() - STAGING_LIFETIME);
+
+    // also matches the bare key earlier versions staged under
+    sqlx::query("delete from blob_chunks where hash >= $1 and hash < $2")
+        .bind(STAGING)
+        .bind(format!("{}:{}", STAGING, cutoff))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Point the file at `dir`/`name` to the blob `hash`, creating the file if
+/// needed, and mark it as written now. Returns the blob it pointed to before,
+/// whose reference the caller now owns and has to either keep or `release`.
+pub async fn link(
+    conn: &mut SqliteConnection,
+    dir: &str,
+    name: &str,
+    hash: &str,
+) -> crate::Result<Option<String>> {
+    let insert_or_update = r#"
+        insert into files
+            (name, path, size, contents, hash, created_at, updated_at)
+        values (
+            $1,
+            $2,
+            (select size from blobs where hash = $3),
+            x'',
+            $3,
+            $4,
+            $4
+        )
+        on conflict(name, path) do
+            update set
+                size = excluded.size,
+                hash = excluded.hash,
+                updated_at = excluded.updated_at
+        "#;
+
+    if !exists(conn, hash).await? {
+        return Err(StorageError::UnknownContent(hash.to_string()))?;
+    }
+
+    let previous: Option<(String,)> =
+        sqlx::query_as("select hash from files where path = $1 and name = $2")
+            .bind(dir)
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    sqlx::query(insert_or_update)
+        .bind(name)
+        .bind(dir)
+        .bind(hash)
+        .bind(sql_timestamp(chrono::Utc::now()))
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("update blobs set refcount = refcount + 1 where hash = $1")
+        .bind(hash)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(previous.map(|(previous,)| previous))
+}
+
+/// Drop one reference to a blob, deleting it once nothing refers to it.
+pub async fn release(conn: &mut SqliteConnection, hash: &str) -> crate::Result<()> {
+    let (refcount,): (i64,) = sqlx::query_as(
+        "update blobs set refcount = refcount - 1 where hash = $1 returning refcount",
+    )
+    .bind(hash)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if refcount <= 0 {
+        sqlx::query("delete from blob_chunks where hash = $1")
+            .bind(hash)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("delete from blobs where hash = $1")
+            .bind(hash)
+            .execute(&mut *conn)
+            .await?;
+
+        storage_search::forget(conn, hash).await?;
+    }
+
+    Ok(())
+}
+
+/// A reference to a blob held while it's read, so its contents aren't dropped
+/// part way through when the files using them are overwritten or removed. The
+/// lease is given back when dropped, or by `expire_leases` once it runs out.
+pub struct BlobLease {
+    pool: sqlx::SqlitePool,
+    id: i64,
+    renew_at: Instant,
+    ended: bool,
+}
+
+impl BlobLease {
+    /// Lease the blob `hash`, or return `None` if there's no such blob.
+    pub async fn take(pool: &sqlx::SqlitePool, hash: &str) -> crate::Result<Option<Self>> {
+        let mut tx = pool.begin().await?;
+
+        let leased = sqlx::query("update blobs set refcount = refcount + 1 where hash = $1")
+            .bind(hash)
+            .execute(&mut *tx)
+            .await?;
+
+        if leased.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let id = sqlx::query("insert into blob_leases (hash, expires_at) values ($1, $2)")
+            .bind(hash)
+            .bind(lease_expiry())
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+
+        tx.commit().await?;
+
+        Ok(Some(Self {
+            pool: pool.clone(),
+            id,
+            renew_at: Instant::now() + LEASE_DURATION / 2,
+            ended: false,
+        }))
+    }
+
+    /// Renew the lease once half of it has run out.
+    pub async fn keep(&mut self) -> crate::Result<()> {
+        if Instant::now() < self.renew_at {
+            return Ok(());
+        }
+
+        sqlx::query("update blob_leases set expires_at = $1 where id = $2")
+            .bind(lease_expiry())
+            .bind(self.id)
+            .execute(&self.pool)
+            .await?;
+
+        self.renew_at = Instant::now() + LEASE_DURATION / 2;
+
+        Ok(())
+    }
+
+    /// Give the lease back now, rather than when it's dropped.
+    pub async fn end(mut self) -> crate::Result<()> {
+        self.ended = true;
+
+        end_lease(&self.pool, self.id).await
+    }
+}
+
+impl Drop for BlobLease {
+    fn drop(&mut self) {
+        if self.ended {
+            return;
+        }
+
+        // a read given up part way through; without a runtime, the lease runs out instead
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let (pool, id) = (self.pool.clone(), self.id);
+
+            runtime.spawn(async move {
+                if let Err(error) = end_lease(&pool, id).await {
+                    tracing::warn!(
+                        "Unable to give back lease {} on a stored blob: {}",
+                        id,
+                        error
+                    );
+                }
+            });
+        }
+    }
+}
+
+fn lease_expiry() -> String {
+    sql_timestamp(chrono::Utc::now() + LEASE_DURATION)
+}
+
+async fn end_lease(pool: &sqlx::SqlitePool, id: i64) -> crate::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let ended: Option<(String,)> =
+        sqlx::query_as("delete from blob_leases where id = $1 returning hash")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    if let Some((hash,)) = ended {
+        release(&mut tx, &hash).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Give back every lease that has run out, left by processes that stopped
+/// while reading.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn expire_leases(pool: &sqlx::SqlitePool) -> crate::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let expired: Vec<(String,)> =
+        sqlx::query_as("delete from blob_leases where expires_at <= $1 returning hash")
+            .bind(sql_timestamp(chrono::Utc::now()))
+            .fetch_all(&mut *tx)
+            .await?;
+
+    for (hash,) in expired {
+        release(&mut tx, &hash).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Replace the placeholder hashes left by the `content_blobs` migration with
+/// real digests, merging any blobs that turn out to be identical.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn rehash_legacy(pool: &sqlx::SqlitePool) -> crate::Result<()> {
+    let legacy: Vec<(String,)> = sqlx::query_as("select hash from blobs where hash like $1")
+        .bind(format!("{}%", LEGACY))
+        .fetch_all(pool)
+        .await?;
+
+    for (placeholder,) in legacy {
+        let mut tx = pool.begin().await?;
+        let mut hasher = Sha256::new();
+        let mut seq = 0;
+
+        while let Some((data,)) = sqlx::query_as::<_, (Vec<u8>,)>(
+            "select data from blob_chunks where hash = $1 and seq = $2",
+        )
+        .bind(&placeholder)
+        .bind(seq)
+        .fetch_optional(&mut *tx)
+        .await?
+        {
+            hasher.update(&data);
+            seq += 1;
+        }
+
+        let hash = hex_digest(hasher);
+
+        tracing::info!("Rehashing legacy storage blob {} as {}", placeholder, hash);
+
+        if exists(&mut tx, &hash).await? {
+            sqlx::query(
+                "update blobs set refcount = refcount + (select refcount from blobs where hash = $2) where hash = $1",
+            )
+            .bind(&hash)
+            .bind(&placeholder)
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query("delete from blob_chunks where hash = $1")
+                .bind(&placeholder)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("delete from blobs where hash = $1")
+                .bind(&placeholder)
+                .execute(&mut *tx)
+                .await?;
+        } else {
+            sqlx::query("update blob_chunks set hash = $1 where hash = $2")
+                .bind(&hash)
+                .bind(&placeholder)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("update blobs set hash = $1 where hash = $2")
+                .bind(&hash)
+                .bind(&placeholder)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query("update files set hash = $1 where hash = $2")
+            .bind(&hash)
+            .bind(&placeholder)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Read from `reader` until `buffer` is full or the reader is exhausted.
+async fn fill_chunk(
+    reader: &mut (impl AsyncRead + Unpin),
+    buffer: &mut [u8],
+) -> crate::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+
+    Ok(filled)
+}
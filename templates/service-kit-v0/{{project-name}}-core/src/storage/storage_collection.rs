@@ -1,58 +1,868 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use super::{StorageFile, StoragePath};
+use futures::{stream::BoxStream, StreamExt};
+
+use sqlx::SqliteConnection;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::broadcast;
+
+use crate::shutdown::Shutdown;
+
+use super::{
+    storage_blob::{self, BlobLease, StagedBlob},
+    storage_event::{self, StorageEvents},
+    storage_maintenance, storage_migration, storage_search, storage_version, StorageBackend,
+    StorageBackupSchedule, StorageBatch, StorageBatchOperation, StorageEntry, StorageError,
+    StorageEvent, StorageEventKind, StorageFile, StorageFileAttributes, StorageFileMeta,
+    StorageFileStream, StorageFileVersion, StorageLimits, StorageMigrationSet,
+    StorageMigrationStatus, StoragePage, StoragePath, StorageSearchHit, StorageStats,
+    STATE_MIGRATIONS, STORAGE_MIGRATIONS,
+};
+
+/// How many times `open` looks a file up again when its contents are
+/// replaced under it, before giving up.
+const OPEN_ATTEMPTS: usize = 3;
 
 #[derive(Clone, Debug)]
 pub struct StorageCollection {
     pub pool: sqlx::SqlitePool,
+    policy: WritePolicy,
+    events: StorageEvents,
+}
+
+/// What governs writes to a collection.
+#[derive(Clone, Debug, Default)]
+struct WritePolicy {
+    /// How many previous versions of each path to keep. Zero turns history off.
+    history: usize,
+    limits: StorageLimits,
+}
+
+/// A write in progress: the policy it follows, and the changes it has made
+/// so far, which are announced once its transaction commits.
+struct PendingWrite<'a> {
+    policy: &'a WritePolicy,
+    events: Vec<StorageEvent>,
+}
+
+impl<'a> PendingWrite<'a> {
+    fn new(policy: &'a WritePolicy) -> Self {
+        Self {
+            policy,
+            events: vec![],
+        }
+    }
+
+    /// Note a change to `path`. Updating a file that was just created or
+    /// updated by the same write isn't news, so it's only noted once.
+    fn record(&mut self, kind: StorageEventKind, path: &StoragePath, size: i64) {
+        let event = StorageEvent::new(kind, path, size);
+        let repeated = self.events.last().is_some_and(|last| {
+            kind == StorageEventKind::Updated
+                && last.path == event.path
+                && last.kind != StorageEventKind::Deleted
+        });
+
+        if !repeated {
+            self.events.push(event);
+        }
+    }
 }
 
 async fn get_connection(url: impl AsRef<str>) -> crate::Result<sqlx::SqlitePool> {
     let pool = sqlx::sqlite::SqlitePool::connect(url.as_ref()).await?;
-    let migration = include_str!("./sql/collection.sql");
-
-    sqlx::query(migration).execute(&pool).await?;
 
     Ok(pool)
 }
 
+/// A row of a directory listing, which may be a file or a synthetic directory.
+#[derive(Debug, sqlx::FromRow)]
+struct ChildRow {
+    key: String,
+    name: String,
+    kind: String,
+    size: Option<i64>,
+    hash: Option<String>,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[sqlx(flatten)]
+    attributes: StorageFileAttributes,
+}
+
+/// The `path` column range holding everything below `dir`: any value starting
+/// with `dir/` sorts within `[lower, upper)`, which lets the index do the work.
+fn descendant_range(dir: &str) -> (String, String) {
+    let lower = if dir == "/" {
+        dir.to_string()
+    } else {
+        format!("{}/", dir)
+    };
+    // '0' is the character immediately after '/'
+    let upper = format!("{}0", &lower[..lower.len() - 1]);
+
+    (lower, upper)
+}
+
+/// Format `time` the way every timestamp is written, matching what
+/// `strftime('%Y-%m-%d %H:%M:%f')` produces, so they can be compared as text
+/// and the `updated_at` index stays usable.
+pub(crate) fn sql_timestamp(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+/// Point `path` at the stored blob `hash`, keeping as much of the contents it
+/// replaces as versions as the policy's `history` allows. Returns whether the
+/// file is new, rather than replacing one.
+async fn link_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+    hash: &str,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<bool> {
+    path.expect_absolute()?;
+
+    let (dir, name) = (path.parent()?.directory(), path.file_name()?);
+    let size: Option<(i64,)> = sqlx::query_as("select size from blobs where hash = $1")
+        .bind(hash)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if let Some((size,)) = size {
+        check_limits_on(conn, &write.policy.limits, path, size, None).await?;
+    }
+
+    let size = size.map_or(0, |(size,)| size);
+
+    match storage_blob::link(conn, &dir, &name, hash).await? {
+        // rewriting the same contents isn't a new version
+        Some(previous) if previous == hash => storage_blob::release(conn, &previous).await?,
+        Some(previous) => {
+            write.record(StorageEventKind::Updated, path, size);
+
+            storage_version::retire(conn, &dir, &name, &previous, write.policy.history).await?
+        }
+        None => {
+            write.record(StorageEventKind::Created, path, size);
+
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Store the staged contents at `path`, as part of whatever transaction
+/// `conn` is in. Returns whether the file is new.
+async fn insert_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+    staged: &mut StagedBlob,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<bool> {
+    path.expect_absolute()?;
+
+    staged.commit(conn).await?;
+
+    link_on(conn, path, &staged.hash, write).await
+}
+
+async fn remove_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<()> {
+    path.expect_absolute()?;
+
+    let removed: Option<(String, i64)> =
+        sqlx::query_as("delete from files where path = $1 and name = $2 returning hash, size")
+            .bind(path.parent()?.directory())
+            .bind(path.file_name()?)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    let (hash, size) = removed.ok_or_else(|| StorageError::FileNotFound(path.to_string()))?;
+
+    write.record(StorageEventKind::Deleted, path, size);
+
+    storage_version::retire(
+        conn,
+        &path.parent()?.directory(),
+        &path.file_name()?,
+        &hash,
+        write.policy.history,
+    )
+    .await
+}
+
+/// Move the file at `from` to `to`, keeping its `created_at`. An existing
+/// file at `to` is replaced if `overwrite` is set, and is a conflict otherwise.
+async fn rename_on(
+    conn: &mut SqliteConnection,
+    from: &StoragePath,
+    to: &StoragePath,
+    overwrite: bool,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<()> {
+    from.expect_absolute()?;
+    to.expect_absolute()?;
+
+    let (id, _) = find_on(conn, from)
+        .await?
+        .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+
+    if from.to_string() == to.to_string() {
+        return Ok(());
+    }
+
+    let (size,): (i64,) = sqlx::query_as("select size from files where id = $1")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    check_limits_on(conn, &write.policy.limits, to, size, Some(from)).await?;
+
+    if find_on(conn, to).await?.is_some() {
+        if !overwrite {
+            return Err(StorageError::FileExists(to.to_string()))?;
+        }
+
+        remove_on(conn, to, write).await?;
+    }
+
+    sqlx::query("update files set path = $1, name = $2 where id = $3")
+        .bind(to.parent()?.directory())
+        .bind(to.file_name()?)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+
+    write.record(StorageEventKind::Deleted, from, size);
+    write.record(StorageEventKind::Created, to, size);
+
+    Ok(())
+}
+
+/// Point `to` at the same stored contents as `from`, with the same overwrite
+/// rules as `rename_on`.
+async fn copy_on(
+    conn: &mut SqliteConnection,
+    from: &StoragePath,
+    to: &StoragePath,
+    overwrite: bool,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<()> {
+    from.expect_absolute()?;
+    to.expect_absolute()?;
+
+    let (_, hash) = find_on(conn, from)
+        .await?
+        .ok_or_else(|| StorageError::FileNotFound(from.to_string()))?;
+
+    if !overwrite && find_on(conn, to).await?.is_some() {
+        return Err(StorageError::FileExists(to.to_string()))?;
+    }
+
+    link_on(conn, to, &hash, write).await?;
+
+    sqlx::query(
+        r#"
+        update files
+        set (content_type, metadata, tags) = (
+            select content_type, metadata, tags from files where path = $3 and name = $4
+        )
+        where path = $1 and name = $2
+        "#,
+    )
+    .bind(to.parent()?.directory())
+    .bind(to.file_name()?)
+    .bind(from.parent()?.directory())
+    .bind(from.file_name()?)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Replace the attributes of the file at `path`. Attributes the file already
+/// has aren't written again, or announced as a change.
+async fn set_attributes_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+    attributes: &StorageFileAttributes,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<()> {
+    path.expect_absolute()?;
+
+    let (dir, name) = (path.parent()?.directory(), path.file_name()?);
+    let current = sqlx::query_as::<_, StorageFileAttributes>(
+        "select content_type, metadata, tags from files where path = $1 and name = $2",
+    )
+    .bind(&dir)
+    .bind(&name)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| StorageError::FileNotFound(path.to_string()))?;
+
+    if &current == attributes {
+        return Ok(());
+    }
+
+    let (size,): (i64,) = sqlx::query_as(
+        "update files set content_type = $1, metadata = $2, tags = $3 where path = $4 and name = $5 returning size",
+    )
+    .bind(&attributes.content_type)
+    .bind(sqlx::types::Json(&attributes.metadata))
+    .bind(sqlx::types::Json(&attributes.tags))
+    .bind(dir)
+    .bind(name)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    write.record(StorageEventKind::Updated, path, size);
+
+    Ok(())
+}
+
+/// Rewrite the path of every file below `from` to sit below `to` instead.
+async fn move_directory_on(
+    conn: &mut SqliteConnection,
+    from: &StoragePath,
+    to: &StoragePath,
+    overwrite: bool,
+    write: &mut PendingWrite<'_>,
+) -> crate::Result<()> {
+    from.expect_absolute()?;
+    to.expect_absolute()?;
+
+    if to.is_within(from) {
+        return Err(StorageError::MoveIntoSelf {
+            from: from.to_string(),
+            to: to.to_string(),
+        })?;
+    }
+
+    let dir = from.directory();
+    let (lower, upper) = descendant_range(&dir);
+    let sources: Vec<StoragePath> = sqlx::query_as::<_, (String, String)>(
+        "select path, name from files where path = $1 or (path >= $2 and path < $3) order by path, name",
+    )
+    .bind(&dir)
+    .bind(&lower)
+    .bind(&upper)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|(path, name)| StoragePath::new(path.into()).join(&name))
+    .collect();
+
+    if sources.is_empty() {
+        return Err(StorageError::DirectoryNotFound(from.to_string()))?;
+    }
+
+    let mut moves = vec![];
+
+    for source in sources.iter() {
+        let target = source
+            .rebase(from, to)
+            .ok_or_else(|| StorageError::BadPath(source.to_string().into()))?;
+
+        // a target that is itself being moved will be out of the way in time
+        let moving = sources.iter().any(|s| s.to_string() == target.to_string());
+
+        if !overwrite && !moving && find_on(conn, &target).await?.is_some() {
+            return Err(StorageError::FileExists(target.to_string()))?;
+        }
+
+        moves.push((source, target));
+    }
+
+    for (source, target) in moves {
+        rename_on(conn, source, &target, true, write).await?;
+    }
+
+    Ok(())
+}
+
+/// Refuse to put a file of `size` bytes at `path` if that would break any of
+/// the quotas covering it. `from` is where the file is moving from, if it's
+/// already stored. Writes that don't add to a quota's usage are let through
+/// even when it's already over.
+async fn check_limits_on(
+    conn: &mut SqliteConnection,
+    limits: &StorageLimits,
+    path: &StoragePath,
+    size: i64,
+    from: Option<&StoragePath>,
+) -> crate::Result<()> {
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    let replaced = replaced_size_on(conn, path).await?;
+
+    for (dir, quota) in limits.quotas_for(path) {
+        if let Some(max) = quota.max_file_size.filter(|max| size as u64 > *max) {
+            return Err(StorageError::FileTooLarge {
+                path: path.to_string(),
+                max,
+            })?;
+        }
+
+        if quota.max_total_size.is_none() && quota.max_files.is_none() {
+            continue;
+        }
+
+        let (bytes, files) = usage_on(conn, &dir).await?;
+
+        // a file moving around inside the directory doesn't add to it
+        let arriving = from.is_none_or(|from| !from.is_within(&dir));
+        let added_bytes = if arriving { size } else { 0 } - replaced.unwrap_or(0);
+        let added_files = i64::from(arriving) - i64::from(replaced.is_some());
+
+        if let Some(max) = quota.max_total_size {
+            if added_bytes > 0 && (bytes + added_bytes) as u64 > max {
+                return Err(StorageError::QuotaExceeded {
+                    dir: dir.directory(),
+                    max,
+                })?;
+            }
+        }
+
+        if let Some(max) = quota.max_files {
+            if added_files > 0 && (files + added_files) as u64 > max {
+                return Err(StorageError::FileLimitReached {
+                    dir: dir.directory(),
+                    max,
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How many bytes can be written to `path` before a quota covering it breaks,
+/// with the error breaking it gives. Fails straight away if a new file would
+/// go over a file count. Contents are read no further than this, but it's
+/// `check_limits_on` that decides once they're linked.
+async fn allowance_on(
+    conn: &mut SqliteConnection,
+    limits: &StorageLimits,
+    path: &StoragePath,
+) -> crate::Result<Option<(u64, StorageError)>> {
+    if limits.is_empty() {
+        return Ok(None);
+    }
+
+    let replaced = replaced_size_on(conn, path).await?;
+    let mut allowance: Option<(u64, StorageError)> = None;
+    let mut tighten = |max: u64, error: StorageError| {
+        if allowance.as_ref().is_none_or(|(current, _)| max < *current) {
+            allowance = Some((max, error));
+        }
+    };
+
+    for (dir, quota) in limits.quotas_for(path) {
+        if let Some(max) = quota.max_file_size {
+            tighten(
+                max,
+                StorageError::FileTooLarge {
+                    path: path.to_string(),
+                    max,
+                },
+            );
+        }
+
+        if quota.max_total_size.is_none() && quota.max_files.is_none() {
+            continue;
+        }
+
+        let (bytes, files) = usage_on(conn, &dir).await?;
+
+        if let Some(max) = quota.max_total_size {
+            // whatever the file replaces can always be written again
+            let free = (max as i64 - bytes).max(0) + replaced.unwrap_or(0);
+
+            tighten(
+                free as u64,
+                StorageError::QuotaExceeded {
+                    dir: dir.directory(),
+                    max,
+                },
+            );
+        }
+
+        if let Some(max) = quota.max_files {
+            if replaced.is_none() && files as u64 >= max {
+                return Err(StorageError::FileLimitReached {
+                    dir: dir.directory(),
+                    max,
+                })?;
+            }
+        }
+    }
+
+    Ok(allowance)
+}
+
+/// The size of the file at `path`, which writing there would replace.
+async fn replaced_size_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+) -> crate::Result<Option<i64>> {
+    let replaced: Option<(i64,)> =
+        sqlx::query_as("select size from files where path = $1 and name = $2")
+            .bind(path.parent()?.directory())
+            .bind(path.file_name()?)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    Ok(replaced.map(|(size,)| size))
+}
+
+/// The total size and number of the files below `dir`.
+async fn usage_on(conn: &mut SqliteConnection, dir: &StoragePath) -> crate::Result<(i64, i64)> {
+    let directory = dir.directory();
+    let (lower, upper) = descendant_range(&directory);
+
+    Ok(sqlx::query_as(
+        "select coalesce(sum(size), 0), count(*) from files where path = $1 or (path >= $2 and path < $3)",
+    )
+    .bind(&directory)
+    .bind(&lower)
+    .bind(&upper)
+    .fetch_one(&mut *conn)
+    .await?)
+}
+
+/// The id and content hash of the file at `path`, if there is one.
+async fn find_on(
+    conn: &mut SqliteConnection,
+    path: &StoragePath,
+) -> crate::Result<Option<(i64, String)>> {
+    Ok(
+        sqlx::query_as("select id, hash from files where path = $1 and name = $2")
+            .bind(path.parent()?.directory())
+            .bind(path.file_name()?)
+            .fetch_optional(&mut *conn)
+            .await?,
+    )
+}
+
 impl StorageCollection {
-    pub async fn file_index(new_db: PathBuf) -> crate::Result<Self> {
+    /// Open the storage database without touching its schema.
+    pub async fn connect(new_db: PathBuf) -> crate::Result<Self> {
         let pool = get_connection(format!("{}", new_db.display())).await?;
-        let collection = StorageCollection { pool };
 
-        collection
-            .insert("/example.md".parse()?, "Hello, world!".as_bytes().into())
-            .await?;
+        Ok(StorageCollection {
+            pool,
+            policy: WritePolicy::default(),
+            events: StorageEvents::default(),
+        })
+    }
+
+    /// Keep up to `history` previous versions of each path when files are
+    /// overwritten or removed.
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.policy.history = history;
+        self
+    }
+
+    /// Refuse writes that would break `limits`.
+    pub fn with_limits(mut self, limits: StorageLimits) -> Self {
+        self.policy.limits = limits;
+        self
+    }
+
+    pub async fn file_index(new_db: PathBuf) -> crate::Result<Self> {
+        let collection = Self::connect(new_db).await?;
+
+        collection.migrate().await?;
 
         Ok(collection)
     }
 
+    /// Changes to files from now on, as they're committed.
+    pub fn subscribe(&self) -> broadcast::Receiver<StorageEvent> {
+        self.events.subscribe()
+    }
+
+    /// Open the state database at `new_db`, with its migrations applied and
+    /// none of the storage ones.
+    pub async fn state_index(new_db: PathBuf) -> crate::Result<Self> {
+        let collection = Self::connect(new_db).await?;
+
+        collection.migrate_state().await?;
+
+        Ok(collection)
+    }
+
+    /// Apply every pending storage migration.
+    pub async fn migrate(&self) -> crate::Result<()> {
+        storage_migration::migrate(&self.pool, &STORAGE_MIGRATIONS).await?;
+        storage_blob::rehash_legacy(&self.pool).await?;
+        storage_blob::expire_leases(&self.pool).await?;
+        storage_blob::discard_abandoned(&self.pool).await?;
+        storage_search::index_pending(&self.pool).await
+    }
+
+    /// Apply every pending state migration, for the cache, queue and
+    /// scheduler.
+    pub async fn migrate_state(&self) -> crate::Result<()> {
+        storage_migration::migrate(&self.pool, &STATE_MIGRATIONS).await
+    }
+
+    /// Revert applied migrations of `set` until `target` is the latest version.
+    pub async fn rollback(&self, set: &StorageMigrationSet, target: i64) -> crate::Result<()> {
+        storage_migration::rollback(&self.pool, set, target).await
+    }
+
+    pub async fn migration_status(
+        &self,
+        set: &StorageMigrationSet,
+    ) -> crate::Result<Vec<StorageMigrationStatus>> {
+        storage_migration::status(&self.pool, set).await
+    }
+
+    /// Copy the whole database to `dest` while it stays in use.
+    pub async fn backup(&self, dest: &Path) -> crate::Result<()> {
+        storage_maintenance::backup(&self.pool, dest).await
+    }
+
+    /// Back up to `schedule.dir` on every tick of its interval, keeping only
+    /// the newest backups. `source` is the database file, which backups are
+    /// named after. Runs until shutdown begins, finishing a backup that's
+    /// underway first; failures are logged and the next tick tries again.
+    pub async fn backup_on_schedule(
+        &self,
+        schedule: StorageBackupSchedule,
+        source: PathBuf,
+        shutdown: &Shutdown,
+    ) {
+        let start = tokio::time::Instant::now() + schedule.interval();
+        let mut interval = tokio::time::interval_at(start, schedule.interval());
+
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.wait() => return,
+            }
+
+            let dest = schedule.next_path(&source);
+            let result = async {
+                tokio::fs::create_dir_all(&schedule.dir).await?;
+                self.backup(&dest).await?;
+                schedule.prune(&source).await
+            }
+            .await;
+
+            match result {
+                Ok(pruned) => tracing::info!(
+                    "Backed up storage to {} ({} old backups removed)",
+                    dest.display(),
+                    pruned.len()
+                ),
+                Err(error) => tracing::error!("Scheduled storage backup failed: {}", error),
+            }
+        }
+    }
+
+    /// Rebuild the database file to give back space left by deleted rows.
+    pub async fn vacuum(&self) -> crate::Result<()> {
+        storage_maintenance::vacuum(&mut *self.pool.acquire().await?).await
+    }
+
+    /// Every problem SQLite finds with the database; empty when it's healthy.
+    pub async fn integrity_check(&self) -> crate::Result<Vec<String>> {
+        storage_maintenance::integrity_check(&mut *self.pool.acquire().await?).await
+    }
+
+    pub async fn stats(&self) -> crate::Result<StorageStats> {
+        storage_maintenance::stats(&mut *self.pool.acquire().await?).await
+    }
+
     pub async fn insert(&self, path: StoragePath, contents: Vec<u8>) -> crate::Result<()> {
-        let insert_or_update = r#"
-            insert into files
-                (name, path, size, contents) 
-            values
-                ($1, $2, $3, $4)
-            on conflict(name, path) do
-                update set
-                    size = excluded.size, 
-                    contents = excluded.contents
-            "#;
+        self.insert_stream(path, contents.as_slice()).await
+    }
+
+    /// Store everything `reader` produces at `path`, one chunk at a time, so
+    /// large files never have to be held in memory all at once.
+    pub async fn insert_stream(
+        &self,
+        path: StoragePath,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> crate::Result<()> {
+        let staged = self.stage(&path, &mut reader).await?;
 
+        self.insert_staged(&path, staged, None).await?;
+
+        Ok(())
+    }
+
+    /// Store everything `reader` produces at `path`, and give the file
+    /// `attributes`, in one transaction. Returns whether the file is new,
+    /// rather than replacing one.
+    pub async fn insert_with(
+        &self,
+        path: StoragePath,
+        mut reader: impl AsyncRead + Unpin,
+        attributes: &StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        let staged = self.stage(&path, &mut reader).await?;
+
+        self.insert_staged(&path, staged, Some(attributes)).await
+    }
+
+    /// Read everything `reader` produces for `path` into staged chunks, before
+    /// any transaction is opened to store it. Reading stops as soon as the
+    /// contents go past what the quotas covering `path` have room for.
+    async fn stage(
+        &self,
+        path: &StoragePath,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> crate::Result<StagedBlob> {
         path.expect_absolute()?;
 
-        sqlx::query(insert_or_update)
-            .bind(&path.file_name()?)
-            .bind(&path.parent()?.to_string())
-            .bind(contents.len() as i64)
-            .bind(contents)
-            .execute(&self.pool)
-            .await?;
+        let allowance =
+            allowance_on(&mut *self.pool.acquire().await?, &self.policy.limits, path).await?;
+        let Some((max, error)) = allowance else {
+            return storage_blob::stage(&self.pool, reader).await;
+        };
+
+        // read just past the allowance, to tell whether it was gone over
+        let staged =
+            storage_blob::stage(&self.pool, &mut reader.take(max.saturating_add(1))).await?;
+
+        if staged.size as u64 > max {
+            staged.discard(&self.pool).await?;
+
+            return Err(error)?;
+        }
+
+        Ok(staged)
+    }
+
+    /// Link staged contents in at `path` in one brief transaction, discarding
+    /// them if that fails. Returns whether the file is new.
+    async fn insert_staged(
+        &self,
+        path: &StoragePath,
+        mut staged: StagedBlob,
+        attributes: Option<&StorageFileAttributes>,
+    ) -> crate::Result<bool> {
+        let mut write = PendingWrite::new(&self.policy);
+        let inserted = async {
+            let mut tx = self.pool.begin().await?;
+
+            let created = insert_on(&mut tx, path, &mut staged, &mut write).await?;
+
+            if let Some(attributes) = attributes {
+                set_attributes_on(&mut tx, path, attributes, &mut write).await?;
+            }
+
+            tx.commit().await?;
+
+            crate::Result::Ok(created)
+        }
+        .await;
+
+        if inserted.is_err() {
+            staged.discard(&self.pool).await?;
+        }
+
+        let created = inserted?;
+        self.events.publish(write.events);
+
+        Ok(created)
+    }
+
+    pub async fn set_attributes(
+        &self,
+        path: StoragePath,
+        attributes: &StorageFileAttributes,
+    ) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        set_attributes_on(&mut tx, &path, attributes, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
 
         Ok(())
     }
 
+    /// Give the file at `path` the times it was created and last written.
+    pub async fn set_times(
+        &self,
+        path: StoragePath,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<()> {
+        path.expect_absolute()?;
+
+        let updated = sqlx::query(
+            "update files set created_at = $1, updated_at = $2 where path = $3 and name = $4",
+        )
+        .bind(sql_timestamp(created_at))
+        .bind(sql_timestamp(updated_at))
+        .bind(path.parent()?.directory())
+        .bind(path.file_name()?)
+        .execute(&self.pool)
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(StorageError::FileNotFound(path.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Every file carrying `tag`, ordered by directory then name.
+    pub async fn find_by_tag(&self, tag: &str) -> crate::Result<Vec<StorageFileMeta>> {
+        let files = sqlx::query_as::<_, StorageFileMeta>(
+            r#"
+            select name, path, size, hash, created_at, updated_at, content_type, metadata, tags
+            from files
+            where exists (select 1 from json_each(files.tags) where value = $1)
+            order by path, name
+            "#,
+        )
+        .bind(tag)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// The files whose contents contain every word of `query`, best matches
+    /// first. Only text files up to a size limit are indexed.
+    pub async fn search(&self, query: &str, limit: usize) -> crate::Result<Vec<StorageSearchHit>> {
+        storage_search::search(&mut *self.pool.acquire().await?, query, limit).await
+    }
+
+    /// Whether contents with the given SHA-256 hash are already stored. Clients
+    /// can check this and `link` to the existing contents instead of uploading.
+    pub async fn contains(&self, hash: &str) -> crate::Result<bool> {
+        storage_blob::exists(&mut *self.pool.acquire().await?, hash).await
+    }
+
+    /// Store a file at `path` whose contents are the already stored blob `hash`.
+    /// Returns whether the file is new, rather than replacing one.
+    pub async fn link(&self, path: StoragePath, hash: &str) -> crate::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        let created = link_on(&mut tx, &path, hash, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
+
+        Ok(created)
+    }
+
     pub async fn len(&self) -> crate::Result<usize> {
         let count: (i64,) = sqlx::query_as("select count(*) from files")
             .fetch_one(&self.pool)
@@ -61,36 +871,600 @@ impl StorageCollection {
         Ok(count.0 as usize)
     }
 
-    pub async fn all(&self) -> crate::Result<Vec<StorageFile>> {
-        let files = sqlx::query_as::<_, StorageFile>("select * from files")
+    /// Metadata for every stored file. Contents are left in the database; use
+    /// `get` to load a particular file.
+    pub async fn all(&self) -> crate::Result<Vec<StorageFileMeta>> {
+        let files = sqlx::query_as::<_, StorageFileMeta>(
+            "select name, path, size, hash, created_at, updated_at, content_type, metadata, tags from files order by path, name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Metadata for a single file, without reading its contents.
+    pub async fn stat(&self, path: StoragePath) -> crate::Result<StorageFileMeta> {
+        path.expect_absolute()?;
+
+        let meta = sqlx::query_as::<_, StorageFileMeta>(
+            "select name, path, size, hash, created_at, updated_at, content_type, metadata, tags from files where path = $1 and name = $2",
+        )
+        .bind(path.parent()?.directory())
+        .bind(path.file_name()?)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(meta.ok_or_else(|| StorageError::FileNotFound(path.to_string()))?)
+    }
+
+    /// Metadata for the files stored directly in `dir`, ordered by name.
+    pub async fn list_meta(&self, dir: StoragePath) -> crate::Result<Vec<StorageFileMeta>> {
+        dir.expect_absolute()?;
+
+        let files = sqlx::query_as::<_, StorageFileMeta>(
+            "select name, path, size, hash, created_at, updated_at, content_type, metadata, tags from files where path = $1 order by name",
+        )
+        .bind(dir.directory())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// List the immediate children of `dir`: the files stored directly in it,
+    /// and a directory entry for each distinct subdirectory below it.
+    pub async fn list(&self, dir: StoragePath) -> crate::Result<Vec<StorageEntry>> {
+        Ok(self.list_page(dir, None, usize::MAX).await?.items)
+    }
+
+    /// Like `list`, but returns at most `limit` entries, starting after `cursor`.
+    pub async fn list_page(
+        &self,
+        dir: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageEntry>> {
+        let children = r#"
+            select key, name, kind, size, hash, created_at, updated_at, content_type, metadata, tags from (
+                select distinct
+                    substr(path, $3, instr(substr(path, $3) || '/', '/') - 1) || '/' as key,
+                    substr(path, $3, instr(substr(path, $3) || '/', '/') - 1) as name,
+                    'directory' as kind,
+                    null as size,
+                    null as hash,
+                    null as created_at,
+                    null as updated_at,
+                    null as content_type,
+                    '{}' as metadata,
+                    '[]' as tags
+                from files
+                where path >= $2 and path < $4 and path <> $1
+                union all
+                select name as key, name, 'file' as kind, size, hash, created_at, updated_at,
+                    content_type, metadata, tags
+                from files
+                where path = $1
+            )
+            where key > $5
+            order by key
+            limit $6
+            "#;
+
+        dir.expect_absolute()?;
+
+        let dir = StoragePath::new(dir.directory().into());
+        let (lower, upper) = descendant_range(&dir.directory());
+
+        let rows = sqlx::query_as::<_, ChildRow>(children)
+            .bind(dir.directory())
+            .bind(&lower)
+            .bind(lower.chars().count() as i64 + 1)
+            .bind(&upper)
+            .bind(cursor.unwrap_or_default())
+            .bind(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                let entry = match (row.kind.as_str(), row.hash, row.created_at, row.updated_at) {
+                    ("file", Some(hash), Some(created_at), Some(updated_at)) => {
+                        StorageEntry::File(StorageFileMeta {
+                            name: row.name,
+                            path: dir.directory(),
+                            size: row.size.unwrap_or_default(),
+                            hash,
+                            created_at,
+                            updated_at,
+                            attributes: row.attributes,
+                        })
+                    }
+                    _ => StorageEntry::Directory {
+                        path: dir.join(&row.name).to_string(),
+                        name: row.name,
+                    },
+                };
+
+                (row.key, entry)
+            })
+            .collect();
+
+        let page = StoragePage::paginate(entries, limit, |(key, _)| key.clone());
+
+        Ok(StoragePage {
+            items: page.items.into_iter().map(|(_, entry)| entry).collect(),
+            cursor: page.cursor,
+        })
+    }
+
+    /// Every file stored anywhere below `prefix`, ordered by directory then name.
+    pub async fn walk(&self, prefix: StoragePath) -> crate::Result<Vec<StorageFileMeta>> {
+        Ok(self.walk_page(prefix, None, usize::MAX).await?.items)
+    }
+
+    /// Like `walk`, but returns at most `limit` files, starting after `cursor`.
+    pub async fn walk_page(
+        &self,
+        prefix: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageFileMeta>> {
+        let descendants = r#"
+            select name, path, size, hash, created_at, updated_at, content_type, metadata, tags
+            from files
+            where (path = $1 or (path >= $2 and path < $3))
+                and (path, name) > ($4, $5)
+            order by path, name
+            limit $6
+            "#;
+
+        prefix.expect_absolute()?;
+
+        let dir = prefix.directory();
+        let (lower, upper) = descendant_range(&dir);
+        let (after_path, after_name) = match cursor {
+            Some(cursor) => {
+                let cursor: StoragePath = cursor.parse()?;
+
+                (cursor.parent()?.directory(), cursor.file_name()?)
+            }
+            None => (String::new(), String::new()),
+        };
+
+        let files = sqlx::query_as::<_, StorageFileMeta>(descendants)
+            .bind(&dir)
+            .bind(&lower)
+            .bind(&upper)
+            .bind(after_path)
+            .bind(after_name)
+            .bind(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX))
             .fetch_all(&self.pool)
             .await?;
 
+        Ok(StoragePage::paginate(files, limit, |meta| {
+            meta.full_path().to_string()
+        }))
+    }
+
+    /// Every file whose contents were written after `since`, oldest change
+    /// first.
+    pub async fn modified_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<Vec<StorageFileMeta>> {
+        let files = sqlx::query_as::<_, StorageFileMeta>(
+            r#"
+            select name, path, size, hash, created_at, updated_at, content_type, metadata, tags
+            from files
+            where updated_at > $1
+            order by updated_at, path, name
+            "#,
+        )
+        .bind(sql_timestamp(since))
+        .fetch_all(&self.pool)
+        .await?;
+
         Ok(files)
     }
 
     pub async fn get(&self, path: StoragePath) -> crate::Result<StorageFile> {
+        self.open(path).await?.read_all().await
+    }
+
+    /// Open a file for streaming. Only its metadata is read up front; contents
+    /// are fetched a chunk at a time as the stream is polled, and stay readable
+    /// until it ends even if the file is overwritten or removed meanwhile.
+    pub async fn open(&self, path: StoragePath) -> crate::Result<StorageFileStream> {
         path.expect_absolute()?;
 
-        let file =
-            sqlx::query_as::<_, StorageFile>("select * from files where path = $1 and name = $2")
-                .bind(&path.parent()?.to_string())
-                .bind(&path.file_name()?)
-                .fetch_one(&self.pool)
-                .await?;
+        for _ in 0..OPEN_ATTEMPTS {
+            let meta = self.stat(path.clone()).await?;
+
+            // the blob is only gone if the file was overwritten since, so read it again
+            if let Some(lease) = BlobLease::take(&self.pool, &meta.hash).await? {
+                return Ok(StorageFileStream::new(self.pool.clone(), meta, lease));
+            }
+        }
 
-        Ok(file)
+        Err(StorageError::IncompleteContents(path.to_string()))?
     }
 
-    pub async fn remove(&self, path: StoragePath) -> crate::Result<()> {
+    /// The kept versions of the file at `path`, newest first.
+    pub async fn versions(&self, path: StoragePath) -> crate::Result<Vec<StorageFileVersion>> {
         path.expect_absolute()?;
 
-        sqlx::query("delete from files where path = $1 and name = $2")
-            .bind(&path.parent()?.to_string())
-            .bind(&path.file_name()?)
-            .execute(&self.pool)
-            .await?;
+        storage_version::list(
+            &mut *self.pool.acquire().await?,
+            &path.parent()?.directory(),
+            &path.file_name()?,
+        )
+        .await
+    }
+
+    async fn version(&self, path: &StoragePath, version: i64) -> crate::Result<StorageFileVersion> {
+        path.expect_absolute()?;
+
+        let found = storage_version::find(
+            &mut *self.pool.acquire().await?,
+            &path.parent()?.directory(),
+            &path.file_name()?,
+            version,
+        )
+        .await?;
+
+        Ok(found.ok_or_else(|| StorageError::VersionNotFound {
+            path: path.to_string(),
+            version,
+        })?)
+    }
+
+    /// Open a previous version of a file for streaming.
+    pub async fn open_version(
+        &self,
+        path: StoragePath,
+        version: i64,
+    ) -> crate::Result<StorageFileStream> {
+        let found = self.version(&path, version).await?;
+        // the blob is only gone if the version was pruned since
+        let lease = BlobLease::take(&self.pool, &found.hash)
+            .await?
+            .ok_or_else(|| StorageError::VersionNotFound {
+                path: path.to_string(),
+                version,
+            })?;
+        let meta = StorageFileMeta {
+            name: found.name,
+            path: found.path,
+            size: found.size,
+            hash: found.hash,
+            created_at: found.replaced_at,
+            updated_at: found.replaced_at,
+            attributes: StorageFileAttributes::default(),
+        };
+
+        Ok(StorageFileStream::new(self.pool.clone(), meta, lease))
+    }
+
+    pub async fn get_version(&self, path: StoragePath, version: i64) -> crate::Result<StorageFile> {
+        self.open_version(path, version).await?.read_all().await
+    }
+
+    /// Make a previous version the current contents of `path`. What it
+    /// replaces is kept as a new version, so a restore can itself be undone.
+    pub async fn restore(&self, path: StoragePath, version: i64) -> crate::Result<()> {
+        let found = self.version(&path, version).await?;
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        link_on(&mut tx, &path, &found.hash, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
 
         Ok(())
     }
+
+    pub async fn remove(&self, path: StoragePath) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        remove_on(&mut tx, &path, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
+
+        Ok(())
+    }
+
+    /// Move the file at `from` to `to`, keeping its `created_at`. If `to`
+    /// already holds a file, this fails with `FileExists` unless `overwrite`
+    /// is set.
+    pub async fn rename(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        rename_on(&mut tx, &from, &to, overwrite, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
+
+        Ok(())
+    }
+
+    /// Store a copy of the file at `from` at `to`, sharing its contents, with
+    /// the same overwrite rules as `rename`.
+    pub async fn copy(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        copy_on(&mut tx, &from, &to, overwrite, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
+
+        Ok(())
+    }
+
+    /// Move every file below the directory `from` to the same place below
+    /// `to` in a single transaction. Without `overwrite`, nothing is moved if
+    /// any of them would replace a file that isn't itself being moved.
+    pub async fn move_directory(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut write = PendingWrite::new(&self.policy);
+
+        move_directory_on(&mut tx, &from, &to, overwrite, &mut write).await?;
+
+        tx.commit().await?;
+        self.events.publish(write.events);
+
+        Ok(())
+    }
+
+    /// Apply every operation in `batch` inside a single transaction.
+    #[tracing::instrument(level = "debug", skip(self, batch))]
+    pub async fn apply_batch(&self, batch: StorageBatch) -> crate::Result<()> {
+        // contents are staged up front, so the transaction only has to link them
+        let mut staged = vec![];
+
+        for operation in batch.operations.iter() {
+            if let StorageBatchOperation::Insert { contents, .. } = operation {
+                // limits are left to the transaction, which sees every operation
+                let staging =
+                    async { storage_blob::stage(&self.pool, &mut contents.reader().await?).await };
+
+                match staging.await {
+                    Ok(blob) => staged.push(blob),
+                    Err(error) => {
+                        self.discard(&staged).await?;
+
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        let mut write = PendingWrite::new(&self.policy);
+        let applied = async {
+            let mut tx = self.pool.begin().await?;
+            let mut blobs = staged.iter_mut();
+
+            for operation in batch.operations.iter() {
+                match operation {
+                    StorageBatchOperation::Insert { path, .. } => {
+                        let blob = blobs.next().expect("every insert is staged");
+
+                        insert_on(&mut tx, path, blob, &mut write).await?;
+                    }
+                    StorageBatchOperation::Remove { path } => {
+                        remove_on(&mut tx, path, &mut write).await?
+                    }
+                    StorageBatchOperation::Rename {
+                        from,
+                        to,
+                        overwrite,
+                    } => rename_on(&mut tx, from, to, *overwrite, &mut write).await?,
+                }
+            }
+
+            tx.commit().await?;
+
+            crate::Result::Ok(())
+        }
+        .await;
+
+        if applied.is_err() {
+            self.discard(&staged).await?;
+        }
+
+        applied?;
+        self.events.publish(write.events);
+
+        Ok(())
+    }
+
+    /// Drop contents staged for a write that failed.
+    async fn discard(&self, staged: &[StagedBlob]) -> crate::Result<()> {
+        for blob in staged {
+            blob.discard(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for StorageCollection {
+    async fn get(&self, path: StoragePath) -> crate::Result<StorageFile> {
+        StorageCollection::get(self, path).await
+    }
+
+    async fn put(&self, path: StoragePath, contents: Vec<u8>) -> crate::Result<()> {
+        self.insert(path, contents).await
+    }
+
+    async fn delete(&self, path: StoragePath) -> crate::Result<()> {
+        self.remove(path).await
+    }
+
+    async fn list(&self, dir: StoragePath) -> crate::Result<Vec<StorageEntry>> {
+        StorageCollection::list(self, dir).await
+    }
+
+    async fn stat(&self, path: StoragePath) -> crate::Result<StorageFileMeta> {
+        StorageCollection::stat(self, path).await
+    }
+
+    async fn put_stream(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> crate::Result<()> {
+        self.insert_stream(path, reader).await
+    }
+
+    async fn put_with(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        self.insert_with(path, reader, &attributes).await
+    }
+
+    async fn set_attributes(
+        &self,
+        path: StoragePath,
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<()> {
+        StorageCollection::set_attributes(self, path, &attributes).await
+    }
+
+    async fn set_times(
+        &self,
+        path: StoragePath,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<()> {
+        StorageCollection::set_times(self, path, created_at, updated_at).await
+    }
+
+    async fn find_by_tag(&self, tag: &str) -> crate::Result<Vec<StorageFileMeta>> {
+        StorageCollection::find_by_tag(self, tag).await
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> crate::Result<Vec<StorageSearchHit>> {
+        StorageCollection::search(self, query, limit).await
+    }
+
+    fn watch(&self, prefix: StoragePath) -> crate::Result<BoxStream<'static, StorageEvent>> {
+        Ok(storage_event::watch(self.subscribe(), prefix).boxed())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+
+    fn as_collection(&self) -> Option<&StorageCollection> {
+        Some(self)
+    }
+
+    async fn open(&self, path: StoragePath) -> crate::Result<StorageFileStream> {
+        StorageCollection::open(self, path).await
+    }
+
+    async fn walk(&self, prefix: StoragePath) -> crate::Result<Vec<StorageFileMeta>> {
+        StorageCollection::walk(self, prefix).await
+    }
+
+    async fn list_page(
+        &self,
+        dir: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageEntry>> {
+        StorageCollection::list_page(self, dir, cursor, limit).await
+    }
+
+    async fn walk_page(
+        &self,
+        prefix: StoragePath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> crate::Result<StoragePage<StorageFileMeta>> {
+        StorageCollection::walk_page(self, prefix, cursor, limit).await
+    }
+
+    async fn rename(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        StorageCollection::rename(self, from, to, overwrite).await
+    }
+
+    async fn copy(&self, from: StoragePath, to: StoragePath, overwrite: bool) -> crate::Result<()> {
+        StorageCollection::copy(self, from, to, overwrite).await
+    }
+
+    async fn move_directory(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        StorageCollection::move_directory(self, from, to, overwrite).await
+    }
+
+    async fn apply_batch(&self, batch: StorageBatch) -> crate::Result<()> {
+        StorageCollection::apply_batch(self, batch).await
+    }
+
+    async fn modified_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<Vec<StorageFileMeta>> {
+        StorageCollection::modified_since(self, since).await
+    }
+
+    async fn versions(&self, path: StoragePath) -> crate::Result<Vec<StorageFileVersion>> {
+        StorageCollection::versions(self, path).await
+    }
+
+    async fn open_version(
+        &self,
+        path: StoragePath,
+        version: i64,
+    ) -> crate::Result<StorageFileStream> {
+        StorageCollection::open_version(self, path, version).await
+    }
+
+    async fn restore(&self, path: StoragePath, version: i64) -> crate::Result<()> {
+        StorageCollection::restore(self, path, version).await
+    }
+
+    async fn contains(&self, hash: &str) -> crate::Result<bool> {
+        StorageCollection::contains(self, hash).await
+    }
+
+    async fn link(&self, path: StoragePath, hash: &str) -> crate::Result<bool> {
+        StorageCollection::link(self, path, hash).await
+    }
 }
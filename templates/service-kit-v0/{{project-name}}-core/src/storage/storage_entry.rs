@@ -0,0 +1,39 @@
+use super::StorageFileMeta;
+
+/// A child of a storage directory: either a stored file, or a directory
+/// implied by the paths of the files stored beneath it.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StorageEntry {
+    File(StorageFileMeta),
+    Directory { name: String, path: String },
+}
+
+impl StorageEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            StorageEntry::File(meta) => &meta.name,
+            StorageEntry::Directory { name, .. } => name,
+        }
+    }
+
+    pub fn path(&self) -> String {
+        match self {
+            StorageEntry::File(meta) => meta.full_path().to_string(),
+            StorageEntry::Directory { path, .. } => path.clone(),
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        matches!(self, StorageEntry::Directory { .. })
+    }
+
+    /// What listings are ordered and paged by: the name, with a trailing
+    /// separator for directories.
+    pub(super) fn cursor_key(&self) -> String {
+        match self {
+            StorageEntry::File(meta) => meta.name.clone(),
+            StorageEntry::Directory { name, .. } => format!("{}/", name),
+        }
+    }
+}
@@ -10,4 +10,56 @@ pub enum StorageError {
     MissingPathData,
     #[error("bad path: {0}")]
     BadPath(PathBuf),
+    #[error("empty path")]
+    EmptyPath,
+    #[error("path reaches above the root: {0}")]
+    PathEscapesRoot(String),
+    #[error("path is {length} bytes long, more than the {max} allowed")]
+    PathTooLong { length: usize, max: usize },
+    #[error("name is more than {max} bytes long: {name}")]
+    NameTooLong { name: String, max: usize },
+    #[error("path contains the control character {character:?}: {path}")]
+    InvalidPathCharacter { path: String, character: char },
+    #[error("path is not valid UTF-8: {0}")]
+    NonUtf8Path(PathBuf),
+    #[error("file not found: {0}")]
+    FileNotFound(String),
+    #[error("version {version} of {path} not found")]
+    VersionNotFound { path: String, version: i64 },
+    #[error("directory not found: {0}")]
+    DirectoryNotFound(String),
+    #[error("file already exists: {0}")]
+    FileExists(String),
+    #[error("cannot move {from} into itself at {to}")]
+    MoveIntoSelf { from: String, to: String },
+    #[error("this storage backend does not keep file attributes")]
+    AttributesUnsupported,
+    #[error("this storage backend does not support search")]
+    SearchUnsupported,
+    #[error("this storage backend does not announce changes")]
+    WatchUnsupported,
+    #[error("only the sqlite storage backend has a database to maintain")]
+    MaintenanceUnsupported,
+    #[error("{path} is larger than the {max} bytes allowed")]
+    FileTooLarge { path: String, max: u64 },
+    #[error("storing this would take {dir} past its quota of {max} bytes")]
+    QuotaExceeded { dir: String, max: u64 },
+    #[error("{dir} already holds the {max} files allowed")]
+    FileLimitReached { dir: String, max: u64 },
+    #[error("invalid storage archive: {0}")]
+    InvalidArchive(String),
+    #[error("archived contents of {0} don't match its manifest")]
+    ArchiveMismatch(String),
+    #[error("backup failed: {0}")]
+    BackupFailed(String),
+    #[error("integrity check found {0} problems")]
+    IntegrityCheckFailed(usize),
+    #[error("stored contents for {0} are incomplete")]
+    IncompleteContents(String),
+    #[error("no stored contents with hash {0}")]
+    UnknownContent(String),
+    #[error("applied migration {version} ({name}) does not match its checksum")]
+    MigrationChecksumMismatch { version: i64, name: String },
+    #[error("applied migration {0} is unknown to this build")]
+    UnknownMigration(i64),
 }
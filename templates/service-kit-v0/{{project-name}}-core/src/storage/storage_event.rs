@@ -0,0 +1,110 @@
+use futures::Stream;
+use tokio::sync::broadcast;
+
+use super::StoragePath;
+
+/// How many events a watcher can fall behind by before it misses some.
+const EVENT_BUFFER: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageEventKind {
+    Created,
+    /// New contents or attributes.
+    Updated,
+    Deleted,
+}
+
+/// A change to a stored file. A rename is the old path being deleted and
+/// the new one created.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StorageEvent {
+    pub kind: StorageEventKind,
+    pub path: String,
+    /// The size of the file, or of what was deleted.
+    pub size: i64,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+impl std::fmt::Display for StorageEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageEventKind::Created => write!(f, "created"),
+            StorageEventKind::Updated => write!(f, "updated"),
+            StorageEventKind::Deleted => write!(f, "deleted"),
+        }
+    }
+}
+
+impl std::fmt::Display for StorageEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ({} bytes)", self.kind, self.path, self.size)
+    }
+}
+
+impl StorageEvent {
+    pub fn new(kind: StorageEventKind, path: &StoragePath, size: i64) -> Self {
+        Self {
+            kind,
+            path: path.to_string(),
+            size,
+            at: chrono::Utc::now(),
+        }
+    }
+
+    /// Whether the event concerns a file at or below `prefix`.
+    pub fn is_within(&self, prefix: &StoragePath) -> bool {
+        StoragePath::new(self.path.clone().into()).is_within(prefix)
+    }
+}
+
+/// Where a backend announces its changes. Events are only sent once the
+/// changes are committed, and are dropped if nobody is watching.
+#[derive(Clone, Debug)]
+pub struct StorageEvents(broadcast::Sender<StorageEvent>);
+
+impl Default for StorageEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(EVENT_BUFFER).0)
+    }
+}
+
+impl StorageEvents {
+    pub fn publish(&self, events: impl IntoIterator<Item = StorageEvent>) {
+        for event in events {
+            // an error only means there are no watchers right now
+            let _ = self.0.send(event);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StorageEvent> {
+        self.0.subscribe()
+    }
+}
+
+/// The events from `receiver` concerning files at or below `prefix`. The
+/// stream ends if the watcher falls so far behind that events were missed,
+/// so that it knows to look at the files afresh rather than trust a gap.
+pub fn watch(
+    receiver: broadcast::Receiver<StorageEvent>,
+    prefix: StoragePath,
+) -> impl Stream<Item = StorageEvent> + Send + 'static {
+    futures::stream::unfold(receiver, move |mut receiver| {
+        let prefix = prefix.clone();
+
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.is_within(&prefix) => return Some((event, receiver)),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Storage watcher missed {} events, closing", missed);
+
+                        return None;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
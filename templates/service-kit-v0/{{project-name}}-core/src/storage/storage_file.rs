@@ -1,16 +1,45 @@
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct StorageFile {
     pub name: String,
     pub path: String,
     pub size: i64,
+    /// Hex-encoded SHA-256 of the contents.
+    pub hash: String,
     pub contents: Vec<u8>,
-    pub created_at: chrono::NaiveDateTime,
-    pub updated_at: chrono::NaiveDateTime,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the contents were last written.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[sqlx(flatten)]
+    pub attributes: super::StorageFileAttributes,
 }
 
 impl StorageFile {
+    pub fn meta(&self) -> super::StorageFileMeta {
+        super::StorageFileMeta {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            size: self.size,
+            hash: self.hash.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            attributes: self.attributes.clone(),
+        }
+    }
+
     #[tracing::instrument(level = "debug", skip(context), name = "Getting storage file for path")]
     pub async fn get(context: &crate::WebContext, path: &str) -> crate::Result<Option<Self>> {
         Ok(context.storage.get(path.parse()?).await.ok())
     }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip(context),
+        name = "Streaming storage file for path"
+    )]
+    pub async fn stream(
+        context: &crate::WebContext,
+        path: &str,
+    ) -> crate::Result<Option<super::StorageFileStream>> {
+        Ok(context.storage.open(path.parse()?).await.ok())
+    }
 }
@@ -0,0 +1,35 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What a stored file carries besides its contents. Writing new contents
+/// leaves a file's attributes as they were; they only change when set.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, sqlx::FromRow,
+)]
+pub struct StorageFileAttributes {
+    /// The MIME type to serve the file as. Guessed from its name when unset.
+    pub content_type: Option<String>,
+    /// Free-form key/value pairs, such as `cache-control` for files served
+    /// to the browser.
+    #[serde(default)]
+    #[sqlx(json)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub tags: BTreeSet<String>,
+}
+
+impl StorageFileAttributes {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The stored content type, or one guessed from `name`.
+    pub fn content_type_for(&self, name: &str) -> String {
+        match &self.content_type {
+            Some(content_type) => content_type.clone(),
+            None => mime_guess::from_path(name)
+                .first_or_octet_stream()
+                .to_string(),
+        }
+    }
+}
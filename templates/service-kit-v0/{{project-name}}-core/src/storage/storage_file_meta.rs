@@ -0,0 +1,25 @@
+use super::{StorageFileAttributes, StoragePath};
+
+/// Everything about a stored file except its contents, so listings never have
+/// to read the `contents` blob.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct StorageFileMeta {
+    pub name: String,
+    pub path: String,
+    pub size: i64,
+    /// Hex-encoded SHA-256 of the contents.
+    pub hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the contents were last written.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub attributes: StorageFileAttributes,
+}
+
+impl StorageFileMeta {
+    /// The full storage path of the file, directory and name together.
+    pub fn full_path(&self) -> StoragePath {
+        StoragePath::new(self.path.clone().into()).join(&self.name)
+    }
+}
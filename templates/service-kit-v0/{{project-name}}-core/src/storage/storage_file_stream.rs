@@ -0,0 +1,128 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::{stream::BoxStream, Stream, StreamExt};
+
+use super::{storage_blob::BlobLease, StorageError, StorageFile, StorageFileMeta};
+
+/// Stored contents are split into chunks of this many bytes.
+pub const CHUNK_SIZE: usize = 512 * 1024;
+
+/// A stored file's contents, read from the backend one chunk at a time.
+pub struct StorageFileStream {
+    pub meta: StorageFileMeta,
+    chunks: BoxStream<'static, crate::Result<Bytes>>,
+}
+
+struct ReadState {
+    pool: sqlx::SqlitePool,
+    hash: String,
+    seq: i64,
+    remaining: i64,
+    lease: BlobLease,
+}
+
+impl StorageFileStream {
+    /// Stream `meta`'s contents from the database. `lease` keeps them readable
+    /// until the stream ends or is dropped, whatever happens to the file.
+    pub(super) fn new(pool: sqlx::SqlitePool, meta: StorageFileMeta, lease: BlobLease) -> Self {
+        let path = meta.full_path().to_string();
+        let state = ReadState {
+            pool,
+            hash: meta.hash.clone(),
+            seq: 0,
+            remaining: meta.size,
+            lease,
+        };
+
+        let chunks = futures::stream::try_unfold(state, move |mut state| {
+            let path = path.clone();
+
+            async move {
+                if state.remaining <= 0 {
+                    state.lease.end().await?;
+
+                    return Ok(None);
+                }
+
+                state.lease.keep().await?;
+
+                let chunk: Option<(Vec<u8>,)> =
+                    sqlx::query_as("select data from blob_chunks where hash = $1 and seq = $2")
+                        .bind(&state.hash)
+                        .bind(state.seq)
+                        .fetch_optional(&state.pool)
+                        .await?;
+
+                let (data,) = chunk.ok_or(StorageError::IncompleteContents(path))?;
+
+                state.seq += 1;
+                state.remaining -= data.len() as i64;
+
+                crate::Result::Ok(Some((Bytes::from(data), state)))
+            }
+        });
+
+        Self::from_stream(meta, chunks)
+    }
+
+    /// Wrap contents a backend already knows how to stream.
+    pub(super) fn from_stream(
+        meta: StorageFileMeta,
+        chunks: impl Stream<Item = crate::Result<Bytes>> + Send + 'static,
+    ) -> Self {
+        Self {
+            meta,
+            chunks: chunks.boxed(),
+        }
+    }
+
+    /// Read the whole stream into memory.
+    pub async fn read_all(mut self) -> crate::Result<StorageFile> {
+        let mut contents = Vec::with_capacity(self.meta.size as usize);
+
+        while let Some(chunk) = self.next().await {
+            contents.extend_from_slice(&chunk?);
+        }
+
+        let meta = self.meta;
+
+        Ok(StorageFile {
+            name: meta.name,
+            path: meta.path,
+            size: meta.size,
+            hash: meta.hash,
+            contents,
+            created_at: meta.created_at,
+            updated_at: meta.updated_at,
+            attributes: meta.attributes,
+        })
+    }
+
+    /// Stream contents that are already in memory, as a single chunk.
+    pub(super) fn from_file(file: StorageFile) -> Self {
+        let meta = file.meta();
+
+        Self::from_stream(
+            meta,
+            futures::stream::once(async move { Ok(Bytes::from(file.contents)) }),
+        )
+    }
+}
+
+impl Stream for StorageFileStream {
+    type Item = crate::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.poll_next_unpin(cx)
+    }
+}
+
+impl std::fmt::Debug for StorageFileStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageFileStream")
+            .field("meta", &self.meta)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use futures::TryStreamExt;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+
+use super::{
+    StorageBackend, StorageEntry, StorageError, StorageFile, StorageFileAttributes,
+    StorageFileMeta, StorageFileStream, StoragePath,
+};
+
+/// Files being written are kept under a name starting with this until
+/// they're complete, and left out of listings.
+const PARTIAL: &str = ".partial-";
+
+/// Keeps each stored file as a plain file below a root directory, so the
+/// contents can be inspected with ordinary tools. Nothing is shared between
+/// files. Hashes are worked out from the contents, and remembered for as long
+/// as the file's size and modification time stay the same. Only contents are
+/// kept: files have no attributes, so content types are always guessed from
+/// names.
+#[derive(Clone, Debug)]
+pub struct FilesystemStorage {
+    root: PathBuf,
+    hashes: Arc<Mutex<HashMap<PathBuf, KnownHash>>>,
+}
+
+/// A file's hash, and what the file looked like when it was worked out.
+#[derive(Debug)]
+struct KnownHash {
+    modified: SystemTime,
+    size: u64,
+    hash: String,
+}
+
+fn timestamp(
+    time: std::io::Result<std::time::SystemTime>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    Some(time.ok()?.into())
+}
+
+/// Missing files are reported the same way by every backend.
+fn not_found(path: &StoragePath) -> impl FnOnce(std::io::Error) -> crate::Error + '_ {
+    move |error| match error.kind() {
+        std::io::ErrorKind::NotFound => StorageError::FileNotFound(path.to_string()).into(),
+        _ => error.into(),
+    }
+}
+
+impl FilesystemStorage {
+    pub fn new(root: PathBuf) -> crate::Result<Self> {
+        std::fs::create_dir_all(&root)?;
+
+        Ok(Self {
+            root,
+            hashes: Arc::default(),
+        })
+    }
+
+    /// Where a storage path lives on disk. Anything that could reach outside
+    /// the root, or be taken for a file still being written, is refused.
+    fn local_path(&self, path: &StoragePath) -> crate::Result<PathBuf> {
+        path.expect_absolute()?;
+
+        let mut local = self.root.clone();
+        let path = path.to_string();
+
+        for component in Path::new(&path).components() {
+            match component {
+                Component::RootDir => {}
+                Component::Normal(name) if !name.to_string_lossy().starts_with(PARTIAL) => {
+                    local.push(name)
+                }
+                _ => return Err(StorageError::BadPath(path.into()))?,
+            }
+        }
+
+        Ok(local)
+    }
+
+    /// Remove `dir` and its parents for as long as they're empty: directories
+    /// only exist while files are stored beneath them.
+    async fn prune(&self, mut dir: Option<&Path>) {
+        while let Some(parent) = dir.filter(|dir| *dir != self.root) {
+            if tokio::fs::remove_dir(parent).await.is_err() {
+                break;
+            }
+
+            dir = parent.parent();
+        }
+    }
+
+    /// The hash of the file at `local`, worked out again only if it has
+    /// changed size or been modified since the last time.
+    async fn hash(&self, local: &Path, metadata: &std::fs::Metadata) -> crate::Result<String> {
+        let modified = metadata.modified()?;
+        let size = metadata.len();
+        let known = self
+            .known_hashes()
+            .get(local)
+            .filter(|known| known.modified == modified && known.size == size)
+            .map(|known| known.hash.clone());
+
+        if let Some(hash) = known {
+            return Ok(hash);
+        }
+
+        let hash = super::file_hash(local).await?;
+
+        self.known_hashes().insert(
+            local.to_path_buf(),
+            KnownHash {
+                modified,
+                size,
+                hash: hash.clone(),
+            },
+        );
+
+        Ok(hash)
+    }
+
+    fn known_hashes(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, KnownHash>> {
+        self.hashes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Store everything `reader` produces at `path`. Returns whether the file
+    /// is new, rather than replacing one.
+    async fn write(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> crate::Result<bool> {
+        path.file_name()?;
+
+        let local = self.local_path(&path)?;
+
+        if let Some(parent) = local.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // written alongside and moved into place, so readers never see it half done
+        let partial = local.with_file_name(format!("{}{:016x}", PARTIAL, rand::random::<u64>()));
+        let written = async {
+            let mut file = tokio::fs::File::create(&partial).await?;
+
+            tokio::io::copy(reader, &mut file).await?;
+            file.flush().await?;
+            file.sync_all().await?;
+
+            // a link is never made over an existing file, so it only succeeds
+            // for a new one; anything else is renamed over what's there
+            match tokio::fs::hard_link(&partial, &local).await {
+                Ok(()) => Ok(true),
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                    tokio::fs::rename(&partial, &local).await.map(|_| false)
+                }
+                Err(error) => Err(error),
+            }
+        }
+        .await;
+
+        let created = match written {
+            Ok(created) => created,
+            Err(error) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+
+                return Err(error)?;
+            }
+        };
+
+        if created {
+            // the file has its own name now; listings skip this one if it stays
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+
+        self.known_hashes().remove(&local);
+
+        Ok(created)
+    }
+
+    async fn meta(&self, path: &StoragePath, local: &Path) -> crate::Result<StorageFileMeta> {
+        let metadata = tokio::fs::metadata(local).await.map_err(not_found(path))?;
+
+        if !metadata.is_file() {
+            return Err(StorageError::FileNotFound(path.to_string()))?;
+        }
+
+        let updated_at = timestamp(metadata.modified()).unwrap_or_default();
+
+        Ok(StorageFileMeta {
+            name: path.file_name()?,
+            path: path.parent()?.directory(),
+            size: metadata.len() as i64,
+            hash: self.hash(local, &metadata).await?,
+            created_at: timestamp(metadata.created()).unwrap_or(updated_at),
+            updated_at,
+            attributes: StorageFileAttributes::default(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for FilesystemStorage {
+    async fn get(&self, path: StoragePath) -> crate::Result<StorageFile> {
+        let local = self.local_path(&path)?;
+        let meta = self.meta(&path, &local).await?;
+        let contents = tokio::fs::read(&local).await.map_err(not_found(&path))?;
+
+        Ok(StorageFile {
+            name: meta.name,
+            path: meta.path,
+            size: meta.size,
+            hash: meta.hash,
+            contents,
+            created_at: meta.created_at,
+            updated_at: meta.updated_at,
+            attributes: meta.attributes,
+        })
+    }
+
+    async fn put(&self, path: StoragePath, contents: Vec<u8>) -> crate::Result<()> {
+        self.put_stream(path, &mut contents.as_slice()).await
+    }
+
+    async fn put_stream(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> crate::Result<()> {
+        self.write(path, reader).await?;
+
+        Ok(())
+    }
+
+    /// There is nowhere to keep `attributes`, so any are refused before
+    /// anything is written.
+    async fn put_with(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        if !attributes.is_empty() {
+            return Err(StorageError::AttributesUnsupported)?;
+        }
+
+        self.write(path, reader).await
+    }
+
+    async fn delete(&self, path: StoragePath) -> crate::Result<()> {
+        let local = self.local_path(&path)?;
+
+        tokio::fs::remove_file(&local)
+            .await
+            .map_err(not_found(&path))?;
+        self.known_hashes().remove(&local);
+
+        self.prune(local.parent()).await;
+
+        Ok(())
+    }
+
+    /// Renames the file on disk, so it keeps its timestamps.
+    async fn rename(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        let source = self.local_path(&from)?;
+        let target = self.local_path(&to)?;
+        let metadata = tokio::fs::metadata(&source)
+            .await
+            .map_err(not_found(&from))?;
+
+        if !metadata.is_file() {
+            return Err(StorageError::FileNotFound(from.to_string()))?;
+        }
+
+        if source == target {
+            return Ok(());
+        }
+
+        to.file_name()?;
+
+        if !overwrite && tokio::fs::metadata(&target).await.is_ok() {
+            return Err(StorageError::FileExists(to.to_string()))?;
+        }
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        tokio::fs::rename(&source, &target).await?;
+
+        {
+            let mut hashes = self.known_hashes();
+            let known = hashes.remove(&source);
+
+            hashes.remove(&target);
+            hashes.extend(known.map(|known| (target, known)));
+        }
+
+        self.prune(source.parent()).await;
+
+        Ok(())
+    }
+
+    async fn list(&self, dir: StoragePath) -> crate::Result<Vec<StorageEntry>> {
+        let dir = StoragePath::new(dir.directory().into());
+        let mut entries = vec![];
+        let mut children = match tokio::fs::read_dir(self.local_path(&dir)?).await {
+            Ok(children) => children,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(error) => return Err(error)?,
+        };
+
+        while let Some(child) = children.next_entry().await? {
+            let Some(name) = child.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+
+            if name.starts_with(PARTIAL) {
+                continue;
+            }
+            let path = dir.join(&name);
+
+            if child.file_type().await?.is_dir() {
+                entries.push(StorageEntry::Directory {
+                    name,
+                    path: path.to_string(),
+                });
+            } else {
+                entries.push(StorageEntry::File(self.meta(&path, &child.path()).await?));
+            }
+        }
+
+        entries.sort_by_key(|entry| entry.cursor_key());
+
+        Ok(entries)
+    }
+
+    async fn stat(&self, path: StoragePath) -> crate::Result<StorageFileMeta> {
+        let local = self.local_path(&path)?;
+
+        self.meta(&path, &local).await
+    }
+
+    async fn open(&self, path: StoragePath) -> crate::Result<StorageFileStream> {
+        let local = self.local_path(&path)?;
+        let meta = self.meta(&path, &local).await?;
+        let file = tokio::fs::File::open(&local)
+            .await
+            .map_err(not_found(&path))?;
+
+        Ok(StorageFileStream::from_stream(
+            meta,
+            tokio_util::io::ReaderStream::new(file).map_err(crate::Error::from),
+        ))
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer};
+
+use super::StoragePath;
+
+/// Bounds on the files stored below one directory. Sizes are in bytes, and
+/// count each file's full size even when its contents are shared. Versions
+/// kept as history don't count.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct StorageQuota {
+    /// The largest file accepted.
+    pub max_file_size: Option<u64>,
+    /// The most that all the files together may add up to.
+    pub max_total_size: Option<u64>,
+    /// The most files there may be.
+    pub max_files: Option<u64>,
+}
+
+/// How much a backend accepts: one quota for everything, plus any number of
+/// quotas for particular directories. A write has to fit every quota that
+/// covers its path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct StorageLimits {
+    #[serde(flatten)]
+    pub all: StorageQuota,
+    /// Quotas for everything below a directory, keyed by its path.
+    #[serde(default, deserialize_with = "prefixes")]
+    pub prefixes: Vec<(StoragePath, StorageQuota)>,
+}
+
+/// Read the `prefixes` table, refusing any key that isn't an absolute path.
+fn prefixes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(StoragePath, StorageQuota)>, D::Error> {
+    BTreeMap::<String, StorageQuota>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(prefix, quota)| {
+            let path: StoragePath = prefix.parse().map_err(serde::de::Error::custom)?;
+
+            path.expect_absolute().map_err(serde::de::Error::custom)?;
+
+            Ok((path, quota))
+        })
+        .collect()
+}
+
+impl StorageLimits {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Every quota covering `path`, with the directory it applies to.
+    pub fn quotas_for(&self, path: &StoragePath) -> Vec<(StoragePath, &StorageQuota)> {
+        let mut quotas = vec![(StoragePath::new("/".into()), &self.all)];
+
+        for (prefix, quota) in &self.prefixes {
+            if path.is_within(prefix) {
+                quotas.push((prefix.clone(), quota));
+            }
+        }
+
+        quotas
+    }
+}
@@ -0,0 +1,298 @@
+//! Looking after the storage database itself: online backups, compaction,
+//! integrity checks and size statistics.
+//!
+//! Backups go through SQLite's online backup API, copying a batch of pages
+//! at a time and pausing in between so writers aren't held up for long. If
+//! another connection writes while a backup is underway, SQLite restarts it
+//! from the top, so the result is always a consistent snapshot.
+
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::time::Duration;
+
+use libsqlite3_sys as ffi;
+use serde::Deserialize;
+use sqlx::{ConnectOptions, Connection, SqliteConnection};
+
+use super::StorageError;
+
+/// How many pages a backup copies before letting other connections in.
+const BACKUP_STEP_PAGES: c_int = 256;
+
+/// How long a backup waits between batches, or before retrying while the
+/// database is locked.
+const BACKUP_PAUSE: Duration = Duration::from_millis(10);
+
+/// Added to a backup's name while it's being written, so an unfinished one is
+/// never mistaken for a backup.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Regular backups taken while the server runs.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StorageBackupSchedule {
+    /// Where backups are written, as `<name>-<timestamp>.db`.
+    pub dir: PathBuf,
+    /// How many minutes to wait between backups.
+    pub every_minutes: u64,
+    /// How many of the newest backups to keep. Older ones are deleted.
+    #[serde(default = "StorageBackupSchedule::default_keep")]
+    pub keep: usize,
+}
+
+impl StorageBackupSchedule {
+    fn default_keep() -> usize {
+        7
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.every_minutes.max(1) * 60)
+    }
+
+    /// A new backup path for the database at `source`, named after it and
+    /// the current time so that backups sort oldest first.
+    pub fn next_path(&self, source: &Path) -> PathBuf {
+        self.dir.join(format!(
+            "{}-{}.db",
+            Self::stem(source),
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+        ))
+    }
+
+    /// Delete all but the newest `keep` backups of `source`, returning the
+    /// paths removed.
+    pub async fn prune(&self, source: &Path) -> crate::Result<Vec<PathBuf>> {
+        let prefix = format!("{}-", Self::stem(source));
+        let mut backups = vec![];
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name.starts_with(&prefix) && name.ends_with(".db") {
+                backups.push(entry.path());
+            }
+        }
+
+        backups.sort();
+
+        let expired = backups.len().saturating_sub(self.keep);
+        let expired: Vec<PathBuf> = backups.drain(..expired).collect();
+
+        for path in &expired {
+            tokio::fs::remove_file(path).await?;
+        }
+
+        Ok(expired)
+    }
+
+    fn stem(source: &Path) -> String {
+        source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "storage".to_string())
+    }
+}
+
+/// How big the storage database is, and what's in it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct StorageStats {
+    pub page_size: i64,
+    pub page_count: i64,
+    /// Pages left empty by deletes, which `vacuum` gives back.
+    pub free_pages: i64,
+    pub files: i64,
+    /// The size of every file added up, counting shared contents once per file.
+    pub total_bytes: i64,
+    /// How many distinct contents are stored, including those kept as versions.
+    pub blobs: i64,
+    /// The size of every distinct contents added up.
+    pub stored_bytes: i64,
+    pub versions: i64,
+}
+
+impl StorageStats {
+    pub fn database_bytes(&self) -> i64 {
+        self.page_size * self.page_count
+    }
+}
+
+impl std::fmt::Display for StorageStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "database: {} bytes ({} pages of {} bytes, {} free)",
+            self.database_bytes(),
+            self.page_count,
+            self.page_size,
+            self.free_pages
+        )?;
+        writeln!(f, "files: {} ({} bytes)", self.files, self.total_bytes)?;
+        writeln!(f, "contents: {} ({} bytes)", self.blobs, self.stored_bytes)?;
+        write!(f, "versions: {}", self.versions)
+    }
+}
+
+/// An in-progress `sqlite3_backup` between two locked connections.
+struct OnlineBackup {
+    raw: NonNull<ffi::sqlite3_backup>,
+    target: NonNull<ffi::sqlite3>,
+}
+
+// SAFETY: a backup is only driven from one task at a time, and only while
+// both of its connections are locked.
+unsafe impl Send for OnlineBackup {}
+
+impl OnlineBackup {
+    /// # Safety
+    ///
+    /// Both handles have to stay locked until the backup is finished.
+    unsafe fn start(
+        source: NonNull<ffi::sqlite3>,
+        target: NonNull<ffi::sqlite3>,
+    ) -> crate::Result<Self> {
+        let raw = ffi::sqlite3_backup_init(
+            target.as_ptr(),
+            c"main".as_ptr(),
+            source.as_ptr(),
+            c"main".as_ptr(),
+        );
+
+        match NonNull::new(raw) {
+            Some(raw) => Ok(Self { raw, target }),
+            None => Err(Self::error(target))?,
+        }
+    }
+
+    fn step(&mut self) -> c_int {
+        // SAFETY: the backup hasn't been finished, since `finish` consumes it.
+        unsafe { ffi::sqlite3_backup_step(self.raw.as_ptr(), BACKUP_STEP_PAGES) }
+    }
+
+    fn finish(self) -> crate::Result<()> {
+        // SAFETY: as for `step`; the backup is never used again.
+        match unsafe { ffi::sqlite3_backup_finish(self.raw.as_ptr()) } {
+            ffi::SQLITE_OK => Ok(()),
+            _ => Err(Self::error(self.target))?,
+        }
+    }
+
+    fn error(handle: NonNull<ffi::sqlite3>) -> StorageError {
+        // SAFETY: SQLite always returns a valid, NUL-terminated message.
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(handle.as_ptr())) };
+
+        StorageError::BackupFailed(message.to_string_lossy().to_string())
+    }
+}
+
+/// Copy the database behind `pool` to a new database at `dest`, without
+/// stopping anyone else from using it. The copy is written alongside under
+/// another name and renamed into place once it's complete, so there's never
+/// a partial backup at `dest`. Anything already there is replaced.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn backup(pool: &sqlx::SqlitePool, dest: &Path) -> crate::Result<()> {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    // left by a backup that stopped part way, and not a database to copy onto
+    match tokio::fs::remove_file(&partial).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error)?,
+        _ => {}
+    }
+
+    if let Err(error) = copy(pool, &partial).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+
+        return Err(error);
+    }
+
+    tokio::fs::rename(&partial, dest).await?;
+
+    Ok(())
+}
+
+/// Run an online backup of `pool` into a new database at `dest`.
+async fn copy(pool: &sqlx::SqlitePool, dest: &Path) -> crate::Result<()> {
+    let mut source = pool.acquire().await?;
+    let mut target = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(dest)
+        .create_if_missing(true)
+        .connect()
+        .await?;
+
+    {
+        let mut source = source.lock_handle().await?;
+        let mut target = target.lock_handle().await?;
+        // SAFETY: both handles are locked until the end of this block, and
+        // the backup is finished before then.
+        let mut backup =
+            unsafe { OnlineBackup::start(source.as_raw_handle(), target.as_raw_handle())? };
+
+        // anything else means done, or failed in a way `finish` reports
+        while let ffi::SQLITE_OK | ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED = backup.step() {
+            tokio::time::sleep(BACKUP_PAUSE).await;
+        }
+
+        backup.finish()?;
+    }
+
+    target.close().await?;
+
+    Ok(())
+}
+
+/// Rebuild the database file, giving back the space left by deleted rows.
+pub async fn vacuum(conn: &mut SqliteConnection) -> crate::Result<()> {
+    sqlx::query("vacuum").execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+/// Every problem SQLite finds with the database; empty when it's healthy.
+pub async fn integrity_check(conn: &mut SqliteConnection) -> crate::Result<Vec<String>> {
+    let results: Vec<(String,)> = sqlx::query_as("pragma integrity_check")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(results
+        .into_iter()
+        .map(|(result,)| result)
+        .filter(|result| result != "ok")
+        .collect())
+}
+
+pub async fn stats(conn: &mut SqliteConnection) -> crate::Result<StorageStats> {
+    let (page_size,): (i64,) = sqlx::query_as("pragma page_size")
+        .fetch_one(&mut *conn)
+        .await?;
+    let (page_count,): (i64,) = sqlx::query_as("pragma page_count")
+        .fetch_one(&mut *conn)
+        .await?;
+    let (free_pages,): (i64,) = sqlx::query_as("pragma freelist_count")
+        .fetch_one(&mut *conn)
+        .await?;
+    let (files, total_bytes): (i64, i64) =
+        sqlx::query_as("select count(*), coalesce(sum(size), 0) from files")
+            .fetch_one(&mut *conn)
+            .await?;
+    let (blobs, stored_bytes): (i64, i64) =
+        sqlx::query_as("select count(*), coalesce(sum(size), 0) from blobs")
+            .fetch_one(&mut *conn)
+            .await?;
+    let (versions,): (i64,) = sqlx::query_as("select count(*) from file_versions")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok(StorageStats {
+        page_size,
+        page_count,
+        free_pages,
+        files,
+        total_bytes,
+        blobs,
+        stored_bytes,
+        versions,
+    })
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{
+    StorageBackend, StorageEntry, StorageError, StorageFile, StorageFileAttributes,
+    StorageFileMeta, StoragePath,
+};
+
+/// Keeps every file in memory, keyed by its full path. Nothing survives the
+/// process, which makes it a good fit for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: RwLock<BTreeMap<String, StorageFile>>,
+}
+
+impl MemoryStorage {
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, StorageFile>> {
+        self.files
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, StorageFile>> {
+        self.files
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Store `contents` at `path` with `attributes`, or with the attributes
+    /// of the file it replaces when there are none. Returns whether the file
+    /// is new.
+    fn store(
+        &self,
+        path: StoragePath,
+        contents: Vec<u8>,
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        path.expect_absolute()?;
+
+        let now = chrono::Utc::now();
+        let mut file = StorageFile {
+            name: path.file_name()?,
+            path: path.parent()?.directory(),
+            size: contents.len() as i64,
+            hash: format!("{:x}", Sha256::digest(&contents)),
+            contents,
+            created_at: now,
+            updated_at: now,
+            attributes,
+        };
+        let mut files = self.write();
+
+        if let Some(previous) = files.get(&path.to_string()) {
+            file.created_at = previous.created_at;
+
+            if file.attributes.is_empty() {
+                file.attributes = previous.attributes.clone();
+            }
+        }
+
+        Ok(files.insert(path.to_string(), file).is_none())
+    }
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn get(&self, path: StoragePath) -> crate::Result<StorageFile> {
+        path.expect_absolute()?;
+
+        let file = self.read().get(&path.to_string()).cloned();
+
+        Ok(file.ok_or_else(|| StorageError::FileNotFound(path.to_string()))?)
+    }
+
+    async fn put(&self, path: StoragePath, contents: Vec<u8>) -> crate::Result<()> {
+        self.store(path, contents, StorageFileAttributes::default())?;
+
+        Ok(())
+    }
+
+    /// Stores the contents and attributes together, under one lock.
+    async fn put_with(
+        &self,
+        path: StoragePath,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<bool> {
+        let mut contents = vec![];
+
+        reader.read_to_end(&mut contents).await?;
+
+        self.store(path, contents, attributes)
+    }
+
+    async fn delete(&self, path: StoragePath) -> crate::Result<()> {
+        path.expect_absolute()?;
+
+        match self.write().remove(&path.to_string()) {
+            Some(_) => Ok(()),
+            None => Err(StorageError::FileNotFound(path.to_string()))?,
+        }
+    }
+
+    async fn set_attributes(
+        &self,
+        path: StoragePath,
+        attributes: StorageFileAttributes,
+    ) -> crate::Result<()> {
+        path.expect_absolute()?;
+
+        match self.write().get_mut(&path.to_string()) {
+            Some(file) => {
+                file.attributes = attributes;
+
+                Ok(())
+            }
+            None => Err(StorageError::FileNotFound(path.to_string()))?,
+        }
+    }
+
+    async fn set_times(
+        &self,
+        path: StoragePath,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> crate::Result<()> {
+        path.expect_absolute()?;
+
+        match self.write().get_mut(&path.to_string()) {
+            Some(file) => {
+                file.created_at = created_at;
+                file.updated_at = updated_at;
+
+                Ok(())
+            }
+            None => Err(StorageError::FileNotFound(path.to_string()))?,
+        }
+    }
+
+    async fn rename(
+        &self,
+        from: StoragePath,
+        to: StoragePath,
+        overwrite: bool,
+    ) -> crate::Result<()> {
+        from.expect_absolute()?;
+        to.expect_absolute()?;
+
+        let (name, path) = (to.file_name()?, to.parent()?.directory());
+        let mut files = self.write();
+
+        if !files.contains_key(&from.to_string()) {
+            return Err(StorageError::FileNotFound(from.to_string()))?;
+        }
+
+        if from.to_string() == to.to_string() {
+            return Ok(());
+        }
+
+        if !overwrite && files.contains_key(&to.to_string()) {
+            return Err(StorageError::FileExists(to.to_string()))?;
+        }
+
+        if let Some(mut file) = files.remove(&from.to_string()) {
+            file.name = name;
+            file.path = path;
+            files.insert(to.to_string(), file);
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, dir: StoragePath) -> crate::Result<Vec<StorageEntry>> {
+        dir.expect_absolute()?;
+
+        let dir = StoragePath::new(dir.directory().into());
+        let prefix = dir.join("").to_string();
+        let mut entries = BTreeMap::new();
+
+        for file in self.read().values() {
+            if file.path == dir.directory() {
+                let entry = StorageEntry::File(file.meta());
+
+                entries.insert(entry.cursor_key(), entry);
+            } else if let Some(rest) = format!("{}/", file.path).strip_prefix(&prefix) {
+                let name = rest.split('/').next().unwrap_or_default().to_string();
+                let entry = StorageEntry::Directory {
+                    path: dir.join(&name).to_string(),
+                    name,
+                };
+
+                entries.insert(entry.cursor_key(), entry);
+            }
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    async fn stat(&self, path: StoragePath) -> crate::Result<StorageFileMeta> {
+        Ok(self.get(path).await?.meta())
+    }
+}
@@ -0,0 +1,328 @@
+use sha2::{Digest, Sha256};
+
+use super::StorageError;
+
+/// A single numbered schema change for one of the databases. Migrations are
+/// applied in ascending `version` order, and each one is recorded in its
+/// set's tracking table along with a checksum of its `up` script.
+#[derive(Clone, Copy, Debug)]
+pub struct StorageMigration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+/// The migrations of one database, tracked in a table of their own so that
+/// both sets can share a database file.
+#[derive(Clone, Copy, Debug)]
+pub struct StorageMigrationSet {
+    pub name: &'static str,
+    table: &'static str,
+    pub migrations: &'static [StorageMigration],
+    /// Versions that moved to another set or were replaced. Databases that
+    /// applied them keep what they did, but their records are ignored.
+    retired: &'static [i64],
+}
+
+/// The files, blobs and versions of the sqlite storage backend.
+pub const STORAGE_MIGRATIONS: StorageMigrationSet = StorageMigrationSet {
+    name: "storage",
+    table: "schema_migrations",
+    migrations: STORAGE,
+    // 5 and 10 to 12 moved to the state set, and 14 and 15 also touched
+    // their tables
+    retired: &[5, 10, 11, 12, 14, 15],
+};
+
+/// What the cache, queue and scheduler keep in the state database.
+pub const STATE_MIGRATIONS: StorageMigrationSet = StorageMigrationSet {
+    name: "state",
+    table: "state_migrations",
+    migrations: STATE,
+    retired: &[],
+};
+
+/// Every storage migration known to this build, in order. New migrations go
+/// at the end with the next version number; applied migrations should never
+/// be edited.
+const STORAGE: &[StorageMigration] = &[
+    StorageMigration {
+        version: 1,
+        name: "create_files",
+        up: include_str!("./sql/migrations/0001_create_files.up.sql"),
+        down: include_str!("./sql/migrations/0001_create_files.down.sql"),
+    },
+    StorageMigration {
+        version: 2,
+        name: "files_path_index",
+        up: include_str!("./sql/migrations/0002_files_path_index.up.sql"),
+        down: include_str!("./sql/migrations/0002_files_path_index.down.sql"),
+    },
+    StorageMigration {
+        version: 3,
+        name: "file_chunks",
+        up: include_str!("./sql/migrations/0003_file_chunks.up.sql"),
+        down: include_str!("./sql/migrations/0003_file_chunks.down.sql"),
+    },
+    StorageMigration {
+        version: 4,
+        name: "content_blobs",
+        up: include_str!("./sql/migrations/0004_content_blobs.up.sql"),
+        down: include_str!("./sql/migrations/0004_content_blobs.down.sql"),
+    },
+    StorageMigration {
+        version: 6,
+        name: "file_versions",
+        up: include_str!("./sql/migrations/0006_file_versions.up.sql"),
+        down: include_str!("./sql/migrations/0006_file_versions.down.sql"),
+    },
+    StorageMigration {
+        version: 7,
+        name: "files_updated_at_index",
+        up: include_str!("./sql/migrations/0007_files_updated_at_index.up.sql"),
+        down: include_str!("./sql/migrations/0007_files_updated_at_index.down.sql"),
+    },
+    StorageMigration {
+        version: 8,
+        name: "file_attributes",
+        up: include_str!("./sql/migrations/0008_file_attributes.up.sql"),
+        down: include_str!("./sql/migrations/0008_file_attributes.down.sql"),
+    },
+    StorageMigration {
+        version: 9,
+        name: "blob_search",
+        up: include_str!("./sql/migrations/0009_blob_search.up.sql"),
+        down: include_str!("./sql/migrations/0009_blob_search.down.sql"),
+    },
+    StorageMigration {
+        version: 13,
+        name: "blob_leases",
+        up: include_str!("./sql/migrations/0013_blob_leases.up.sql"),
+        down: include_str!("./sql/migrations/0013_blob_leases.down.sql"),
+    },
+    StorageMigration {
+        version: 16,
+        name: "uniform_timestamps",
+        up: include_str!("./sql/migrations/0016_uniform_timestamps.up.sql"),
+        down: include_str!("./sql/migrations/0016_uniform_timestamps.down.sql"),
+    },
+];
+
+/// Every state migration known to this build, in order, with the same rules.
+const STATE: &[StorageMigration] = &[
+    StorageMigration {
+        version: 1,
+        name: "cache_entries",
+        up: include_str!("./sql/state/0001_cache_entries.up.sql"),
+        down: include_str!("./sql/state/0001_cache_entries.down.sql"),
+    },
+    StorageMigration {
+        version: 2,
+        name: "queue_jobs",
+        up: include_str!("./sql/state/0002_queue_jobs.up.sql"),
+        down: include_str!("./sql/state/0002_queue_jobs.down.sql"),
+    },
+    StorageMigration {
+        version: 3,
+        name: "scheduled_jobs",
+        up: include_str!("./sql/state/0003_scheduled_jobs.up.sql"),
+        down: include_str!("./sql/state/0003_scheduled_jobs.down.sql"),
+    },
+    StorageMigration {
+        version: 4,
+        name: "uniform_timestamps",
+        up: include_str!("./sql/state/0004_uniform_timestamps.up.sql"),
+        down: include_str!("./sql/state/0004_uniform_timestamps.down.sql"),
+    },
+    StorageMigration {
+        version: 5,
+        name: "storage_seeds",
+        up: include_str!("./sql/state/0005_storage_seeds.up.sql"),
+        down: include_str!("./sql/state/0005_storage_seeds.down.sql"),
+    },
+];
+
+impl StorageMigration {
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.up.as_bytes()))
+    }
+}
+
+/// Where a known migration stands against a given database.
+#[derive(Debug)]
+pub struct StorageMigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<chrono::NaiveDateTime>,
+}
+
+impl std::fmt::Display for StorageMigrationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.applied_at {
+            Some(applied_at) => write!(
+                f,
+                "{:04} {} (applied {})",
+                self.version, self.name, applied_at
+            ),
+            None => write!(f, "{:04} {} (pending)", self.version, self.name),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct AppliedMigration {
+    version: i64,
+    checksum: String,
+    applied_at: chrono::NaiveDateTime,
+}
+
+async fn ensure_tracking_table(
+    pool: &sqlx::SqlitePool,
+    set: &StorageMigrationSet,
+) -> crate::Result<()> {
+    let create_table = format!(
+        r#"
+        create table if not exists {} (
+            version    integer    primary key,
+            name       text       not null,
+            checksum   text       not null,
+            applied_at timestamp  default current_timestamp
+        )
+        "#,
+        set.table
+    );
+
+    sqlx::query(&create_table).execute(pool).await?;
+
+    Ok(())
+}
+
+/// Load the set's applied migrations, verifying each one against the known
+/// set.
+async fn applied(
+    pool: &sqlx::SqlitePool,
+    set: &StorageMigrationSet,
+) -> crate::Result<Vec<AppliedMigration>> {
+    ensure_tracking_table(pool, set).await?;
+
+    let applied = sqlx::query_as::<_, AppliedMigration>(&format!(
+        "select version, checksum, applied_at from {} order by version",
+        set.table
+    ))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter(|record| !set.retired.contains(&record.version))
+    .collect::<Vec<_>>();
+
+    for record in &applied {
+        let migration = set
+            .migrations
+            .iter()
+            .find(|m| m.version == record.version)
+            .ok_or(StorageError::UnknownMigration(record.version))?;
+
+        if migration.checksum() != record.checksum {
+            return Err(StorageError::MigrationChecksumMismatch {
+                version: migration.version,
+                name: migration.name.to_string(),
+            })?;
+        }
+    }
+
+    Ok(applied)
+}
+
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn migrate(pool: &sqlx::SqlitePool, set: &StorageMigrationSet) -> crate::Result<()> {
+    let applied = applied(pool, set).await?;
+
+    for migration in set
+        .migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+    {
+        tracing::info!(
+            "Applying {} migration {} ({})",
+            set.name,
+            migration.version,
+            migration.name
+        );
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(migration.up).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "insert into {} (version, name, checksum, applied_at) values ($1, $2, $3, $4)",
+            set.table
+        ))
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .bind(super::sql_timestamp(chrono::Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Revert every applied migration of the set newer than `target`, newest
+/// first.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn rollback(
+    pool: &sqlx::SqlitePool,
+    set: &StorageMigrationSet,
+    target: i64,
+) -> crate::Result<()> {
+    let applied = applied(pool, set).await?;
+
+    for migration in set
+        .migrations
+        .iter()
+        .rev()
+        .filter(|m| m.version > target && applied.iter().any(|a| a.version == m.version))
+    {
+        tracing::info!(
+            "Reverting {} migration {} ({})",
+            set.name,
+            migration.version,
+            migration.name
+        );
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(migration.down).execute(&mut *tx).await?;
+        sqlx::query(&format!("delete from {} where version = $1", set.table))
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+pub async fn status(
+    pool: &sqlx::SqlitePool,
+    set: &StorageMigrationSet,
+) -> crate::Result<Vec<StorageMigrationStatus>> {
+    let applied = applied(pool, set).await?;
+
+    Ok(set
+        .migrations
+        .iter()
+        .map(|migration| StorageMigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied_at: applied
+                .iter()
+                .find(|a| a.version == migration.version)
+                .map(|a| a.applied_at),
+        })
+        .collect())
+}
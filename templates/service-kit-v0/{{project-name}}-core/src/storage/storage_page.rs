@@ -0,0 +1,26 @@
+/// One page of a listing. Pass `cursor` back into the same query to fetch the
+/// next page; it is `None` once the listing is exhausted.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct StoragePage<T> {
+    pub items: Vec<T>,
+    pub cursor: Option<String>,
+}
+
+impl<T> StoragePage<T> {
+    /// Build a page from up to `limit + 1` items, using the extra item only to
+    /// decide whether there's another page to fetch.
+    pub(super) fn paginate(
+        mut items: Vec<T>,
+        limit: usize,
+        cursor: impl Fn(&T) -> String,
+    ) -> StoragePage<T> {
+        let cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+
+        StoragePage { items, cursor }
+    }
+}
@@ -2,11 +2,24 @@ use std::path::PathBuf;
 
 use super::StorageError;
 
+/// The longest path accepted, in bytes, once normalized.
+pub const MAX_PATH_LENGTH: usize = 4096;
+
+/// The longest single file or directory name accepted, in bytes.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// A path to a stored file or directory. Parsing normalizes it, so the same
+/// location is always spelled the same way: `\` is read as `/`, repeated
+/// separators collapse, `.` and `..` are resolved, and there is no trailing
+/// separator. Anything that would climb above where it starts is refused.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StoragePath(PathBuf);
 
 impl StoragePath {
+    /// Wrap a path that is already normalized, such as one read back from
+    /// storage. Paths from anywhere else should be parsed instead.
     pub fn new(path: PathBuf) -> Self {
-        StoragePath::from(path)
+        StoragePath(path)
     }
 
     fn into_inner(&self) -> PathBuf {
@@ -28,6 +41,36 @@ impl StoragePath {
             .ok_or(StorageError::MissingPathData)
     }
 
+    /// The path as a directory, in the form stored in the `path` column
+    /// (no trailing separator, except for the root itself).
+    pub fn directory(&self) -> String {
+        let path = self.to_string();
+        let trimmed = path.trim_end_matches('/');
+
+        if trimmed.is_empty() {
+            "/".to_string()
+        } else {
+            trimmed.to_string()
+        }
+    }
+
+    /// The path joined with a child name.
+    pub fn join(&self, name: &str) -> StoragePath {
+        StoragePath(self.0.join(name))
+    }
+
+    /// Whether this path is `dir` itself or anywhere below it.
+    pub fn is_within(&self, dir: &StoragePath) -> bool {
+        self.0.starts_with(&dir.0)
+    }
+
+    /// This path moved from below `from` to the same place below `to`.
+    pub fn rebase(&self, from: &StoragePath, to: &StoragePath) -> Option<StoragePath> {
+        let rest = self.0.strip_prefix(&from.0).ok()?;
+
+        Some(StoragePath(to.0.join(rest)))
+    }
+
     pub fn file_name(&self) -> Result<String, StorageError> {
         self.0
             .file_name()
@@ -41,7 +84,52 @@ impl std::str::FromStr for StoragePath {
     type Err = StorageError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(StoragePath(PathBuf::from(s)))
+        if s.is_empty() {
+            return Err(StorageError::EmptyPath);
+        }
+
+        if let Some(character) = s.chars().find(|c| c.is_control()) {
+            return Err(StorageError::InvalidPathCharacter {
+                path: s.escape_debug().to_string(),
+                character,
+            });
+        }
+
+        let absolute = s.starts_with(['/', '\\']);
+        let mut names: Vec<&str> = vec![];
+
+        for name in s.split(['/', '\\']) {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    if names.pop().is_none() {
+                        return Err(StorageError::PathEscapesRoot(s.to_string()));
+                    }
+                }
+                name if name.len() > MAX_NAME_LENGTH => {
+                    return Err(StorageError::NameTooLong {
+                        name: name.to_string(),
+                        max: MAX_NAME_LENGTH,
+                    });
+                }
+                name => names.push(name),
+            }
+        }
+
+        let path = match (absolute, names.is_empty()) {
+            (true, _) => format!("/{}", names.join("/")),
+            (false, true) => return Err(StorageError::EmptyPath),
+            (false, false) => names.join("/"),
+        };
+
+        if path.len() > MAX_PATH_LENGTH {
+            return Err(StorageError::PathTooLong {
+                length: path.len(),
+                max: MAX_PATH_LENGTH,
+            });
+        }
+
+        Ok(StoragePath(PathBuf::from(path)))
     }
 }
 
@@ -51,8 +139,13 @@ impl std::fmt::Display for StoragePath {
     }
 }
 
-impl From<PathBuf> for StoragePath {
-    fn from(path: PathBuf) -> Self {
-        StoragePath(path)
+impl TryFrom<PathBuf> for StoragePath {
+    type Error = StorageError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        match path.to_str() {
+            Some(path) => path.parse(),
+            None => Err(StorageError::NonUtf8Path(path)),
+        }
     }
 }
@@ -0,0 +1,147 @@
+//! Full-text search over stored contents. Text is indexed per blob in the
+//! `blob_text` FTS5 table, so contents shared by several files are indexed
+//! once, and a search finds every file pointing at a matching blob. A blob
+//! counts as text if it's valid UTF-8 without NUL bytes and no larger than
+//! `MAX_INDEXED_SIZE`; `blobs.searchable` records whether it was indexed, and
+//! is null for blobs that haven't been looked at yet.
+
+use sqlx::SqliteConnection;
+
+use super::StorageFileMeta;
+
+/// Blobs larger than this many bytes are never indexed.
+pub const MAX_INDEXED_SIZE: usize = 1024 * 1024;
+
+/// A stored file whose contents match a search.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct StorageSearchHit {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub file: StorageFileMeta,
+    /// A short excerpt around the match, with matched terms in `[` `]`.
+    pub snippet: String,
+    /// How well the file matches; higher is better.
+    pub score: f64,
+}
+
+/// The text of `contents`, if it's worth indexing.
+fn searchable_text(contents: Vec<u8>) -> Option<String> {
+    if contents.len() > MAX_INDEXED_SIZE || contents.contains(&0) {
+        return None;
+    }
+
+    String::from_utf8(contents).ok()
+}
+
+/// Turn free text into an FTS5 query matching every word in it, so quotes
+/// and operators typed by users are searched for rather than interpreted.
+fn match_expression(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Index the blob `hash`, given its contents, or `None` if they were too
+/// large to keep.
+pub async fn index(
+    conn: &mut SqliteConnection,
+    hash: &str,
+    contents: Option<Vec<u8>>,
+) -> crate::Result<()> {
+    let text = contents.and_then(searchable_text);
+
+    if let Some(text) = &text {
+        sqlx::query("insert into blob_text (hash, body) values ($1, $2)")
+            .bind(hash)
+            .bind(text)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query("update blobs set searchable = $1 where hash = $2")
+        .bind(text.is_some())
+        .bind(hash)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Drop the blob `hash` from the index.
+pub async fn forget(conn: &mut SqliteConnection, hash: &str) -> crate::Result<()> {
+    sqlx::query("delete from blob_text where hash = $1")
+        .bind(hash)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Index every blob that hasn't been looked at yet, such as those stored
+/// before the index existed.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn index_pending(pool: &sqlx::SqlitePool) -> crate::Result<()> {
+    let pending: Vec<(String, i64)> =
+        sqlx::query_as("select hash, size from blobs where searchable is null")
+            .fetch_all(pool)
+            .await?;
+
+    for (hash, size) in pending {
+        let mut tx = pool.begin().await?;
+        let contents = if size as usize <= MAX_INDEXED_SIZE {
+            let chunks: Vec<(Vec<u8>,)> =
+                sqlx::query_as("select data from blob_chunks where hash = $1 order by seq")
+                    .bind(&hash)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+            Some(chunks.into_iter().flat_map(|(data,)| data).collect())
+        } else {
+            None
+        };
+
+        tracing::debug!("Indexing storage blob {} for search", hash);
+
+        index(&mut tx, &hash, contents).await?;
+
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// The files whose contents contain every word of `query`, best matches first.
+pub async fn search(
+    conn: &mut SqliteConnection,
+    query: &str,
+    limit: usize,
+) -> crate::Result<Vec<StorageSearchHit>> {
+    let expression = match_expression(query);
+
+    if expression.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let hits = sqlx::query_as::<_, StorageSearchHit>(
+        r#"
+        select
+            f.name, f.path, f.size, f.hash, f.created_at, f.updated_at,
+            f.content_type, f.metadata, f.tags,
+            snippet(blob_text, 1, '[', ']', '…', 12) as snippet,
+            -bm25(blob_text) as score
+        from blob_text
+        join files f on f.hash = blob_text.hash
+        where blob_text match $1
+        order by bm25(blob_text), f.path, f.name
+        limit $2
+        "#,
+    )
+    .bind(expression)
+    .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(hits)
+}
@@ -0,0 +1,100 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use tokio::io::AsyncRead;
+
+use super::{
+    sql_timestamp, StorageBackend, StorageCollection, StorageError, StorageSyncAction,
+    StorageSyncPlan,
+};
+
+/// Files to put into fresh storage. Each path is seeded at most once: after
+/// that it's recorded in the state database's `storage_seeds` and left alone,
+/// even if it's later edited or removed.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StorageSeed {
+    /// A local directory whose files are seeded at the same paths below the root.
+    pub dir: Option<PathBuf>,
+    /// Files given inline.
+    #[serde(default)]
+    pub files: Vec<StorageSeedFile>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StorageSeedFile {
+    pub path: String,
+    pub contents: String,
+}
+
+/// Store every file in `seed` that hasn't been seeded into `storage` before,
+/// recording each in `state`, and return the paths that were written.
+#[tracing::instrument(level = "debug", skip(storage, state, seed))]
+pub async fn seed(
+    storage: &dyn StorageBackend,
+    state: &StorageCollection,
+    seed: &StorageSeed,
+) -> crate::Result<Vec<String>> {
+    let mut seeded = vec![];
+
+    if let Some(dir) = &seed.dir {
+        let plan = StorageSyncPlan::build(dir, &"/".parse()?, vec![], &Default::default()).await?;
+
+        for action in plan.actions {
+            if let StorageSyncAction::Create { local, remote } = action {
+                let mut file = tokio::fs::File::open(local).await?;
+
+                if seed_file(storage, state, &remote, &mut file).await? {
+                    seeded.push(remote);
+                }
+            }
+        }
+    }
+
+    for file in &seed.files {
+        if seed_file(storage, state, &file.path, &mut file.contents.as_bytes()).await? {
+            seeded.push(file.path.clone());
+        }
+    }
+
+    Ok(seeded)
+}
+
+/// Seed a single path unless it was seeded before, returning whether anything
+/// was written. Paths that already hold a file are recorded without being
+/// overwritten.
+async fn seed_file(
+    storage: &dyn StorageBackend,
+    state: &StorageCollection,
+    path: &str,
+    contents: &mut (dyn AsyncRead + Unpin + Send),
+) -> crate::Result<bool> {
+    let applied: Option<(String,)> =
+        sqlx::query_as("select path from storage_seeds where path = $1")
+            .bind(path)
+            .fetch_optional(&state.pool)
+            .await?;
+
+    if applied.is_some() {
+        return Ok(false);
+    }
+
+    let written = match storage.stat(path.parse()?).await {
+        Ok(_) => false,
+        Err(crate::Error::StorageError(StorageError::FileNotFound(_))) => {
+            tracing::info!("Seeding storage file {}", path);
+
+            storage.put_stream(path.parse()?, contents).await?;
+
+            true
+        }
+        Err(error) => return Err(error),
+    };
+
+    sqlx::query("insert into storage_seeds (path, applied_at) values ($1, $2)")
+        .bind(path)
+        .bind(sql_timestamp(chrono::Utc::now()))
+        .execute(&state.pool)
+        .await?;
+
+    Ok(written)
+}
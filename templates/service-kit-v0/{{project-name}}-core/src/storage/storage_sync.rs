@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{StorageError, StorageFileMeta, StoragePath};
+
+#[derive(Clone, Debug, Default)]
+pub struct StorageSyncOptions {
+    /// Delete stored files below the prefix that have no local counterpart.
+    pub delete: bool,
+    /// Always compare contents by hash, even when size and modification time
+    /// suggest a file is unchanged.
+    pub checksum: bool,
+}
+
+/// One step needed to make a storage prefix match a local directory.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum StorageSyncAction {
+    Create { local: PathBuf, remote: String },
+    Update { local: PathBuf, remote: String },
+    Delete { remote: String },
+}
+
+impl std::fmt::Display for StorageSyncAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageSyncAction::Create { local, remote } => {
+                write!(f, "create {} <- {}", remote, local.display())
+            }
+            StorageSyncAction::Update { local, remote } => {
+                write!(f, "update {} <- {}", remote, local.display())
+            }
+            StorageSyncAction::Delete { remote } => write!(f, "delete {}", remote),
+        }
+    }
+}
+
+/// The difference between a local directory and the files stored under a
+/// prefix, as the actions that would reconcile them.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct StorageSyncPlan {
+    pub actions: Vec<StorageSyncAction>,
+}
+
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    modified: chrono::DateTime<chrono::Utc>,
+}
+
+/// Every regular file below `root`, keyed by its storage path under `prefix`.
+async fn local_files(root: &Path, prefix: &StoragePath) -> crate::Result<Vec<(String, LocalFile)>> {
+    let mut files = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                let path = entry.path();
+                let mut remote = prefix.clone();
+
+                for component in path.strip_prefix(root).unwrap_or(&path).components() {
+                    let name = component
+                        .as_os_str()
+                        .to_str()
+                        .ok_or(StorageError::InvalidFileName)?;
+
+                    remote = remote.join(name);
+                }
+
+                let metadata = entry.metadata().await?;
+                files.push((
+                    remote.to_string(),
+                    LocalFile {
+                        path,
+                        size: metadata.len(),
+                        modified: metadata.modified()?.into(),
+                    },
+                ));
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(files)
+}
+
+impl StorageSyncPlan {
+    /// Compare the files below `local` with the `remote` files stored under
+    /// `prefix`. Files whose size differs are changed; files of the same size
+    /// are assumed unchanged unless modified locally since they were stored,
+    /// in which case their hashes decide.
+    pub async fn build(
+        local: &Path,
+        prefix: &StoragePath,
+        remote: Vec<StorageFileMeta>,
+        options: &StorageSyncOptions,
+    ) -> crate::Result<Self> {
+        let mut remote: HashMap<String, StorageFileMeta> = remote
+            .into_iter()
+            .map(|meta| (meta.full_path().to_string(), meta))
+            .collect();
+        let mut actions = vec![];
+
+        for (path, file) in local_files(local, prefix).await? {
+            let action = match remote.remove(&path) {
+                None => Some(StorageSyncAction::Create {
+                    local: file.path,
+                    remote: path,
+                }),
+                Some(meta) => {
+                    let changed = if file.size != meta.size as u64 {
+                        true
+                    } else if options.checksum || file.modified > meta.updated_at {
+                        super::file_hash(&file.path).await? != meta.hash
+                    } else {
+                        false
+                    };
+
+                    changed.then_some(StorageSyncAction::Update {
+                        local: file.path,
+                        remote: path,
+                    })
+                }
+            };
+
+            actions.extend(action);
+        }
+
+        if options.delete {
+            let mut extras: Vec<_> = remote.into_keys().collect();
+
+            extras.sort();
+            actions.extend(
+                extras
+                    .into_iter()
+                    .map(|remote| StorageSyncAction::Delete { remote }),
+            );
+        }
+
+        Ok(Self { actions })
+    }
+}
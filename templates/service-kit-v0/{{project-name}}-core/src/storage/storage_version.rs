@@ -0,0 +1,119 @@
+//! Prior revisions of stored files. When a file is overwritten or removed, its
+//! old contents can be kept in `file_versions` instead of being released. Each
+//! version holds its own reference to its blob, and only the newest `history`
+//! versions of a path are kept. History belongs to a path, not to a file, so
+//! it stays behind when a file is renamed.
+
+use sqlx::SqliteConnection;
+
+use super::storage_blob;
+
+/// A previous revision of the file at `path`/`name`. Versions are numbered
+/// from 1 per path, and numbers are never reused.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct StorageFileVersion {
+    pub name: String,
+    pub path: String,
+    pub version: i64,
+    pub size: i64,
+    pub hash: String,
+    /// When these contents stopped being the current ones.
+    pub replaced_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Hand over a reference to `hash`, the contents `dir`/`name` held until now.
+/// With `history` enabled they're kept as the path's newest version; otherwise
+/// the reference is simply released.
+pub async fn retire(
+    conn: &mut SqliteConnection,
+    dir: &str,
+    name: &str,
+    hash: &str,
+    history: usize,
+) -> crate::Result<()> {
+    if history == 0 {
+        return storage_blob::release(conn, hash).await;
+    }
+
+    let record = r#"
+        insert into file_versions
+            (path, name, version, hash, size, replaced_at)
+        values (
+            $1,
+            $2,
+            coalesce((select max(version) from file_versions where path = $1 and name = $2), 0) + 1,
+            $3,
+            (select size from blobs where hash = $3),
+            $4
+        )
+        "#;
+
+    sqlx::query(record)
+        .bind(dir)
+        .bind(name)
+        .bind(hash)
+        .bind(super::sql_timestamp(chrono::Utc::now()))
+        .execute(&mut *conn)
+        .await?;
+
+    let expired: Vec<(String,)> = sqlx::query_as(
+        r#"
+        delete from file_versions
+        where path = $1 and name = $2 and version <= (
+            select max(version) from file_versions where path = $1 and name = $2
+        ) - $3
+        returning hash
+        "#,
+    )
+    .bind(dir)
+    .bind(name)
+    .bind(i64::try_from(history).unwrap_or(i64::MAX))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for (hash,) in expired {
+        storage_blob::release(conn, &hash).await?;
+    }
+
+    Ok(())
+}
+
+/// Every kept version of `dir`/`name`, newest first.
+pub async fn list(
+    conn: &mut SqliteConnection,
+    dir: &str,
+    name: &str,
+) -> crate::Result<Vec<StorageFileVersion>> {
+    Ok(sqlx::query_as(
+        r#"
+        select name, path, version, size, hash, replaced_at
+        from file_versions
+        where path = $1 and name = $2
+        order by version desc
+        "#,
+    )
+    .bind(dir)
+    .bind(name)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+pub async fn find(
+    conn: &mut SqliteConnection,
+    dir: &str,
+    name: &str,
+    version: i64,
+) -> crate::Result<Option<StorageFileVersion>> {
+    Ok(sqlx::query_as(
+        r#"
+        select name, path, version, size, hash, replaced_at
+        from file_versions
+        where path = $1 and name = $2 and version = $3
+        "#,
+    )
+    .bind(dir)
+    .bind(name)
+    .bind(version)
+    .fetch_optional(&mut *conn)
+    .await?)
+}
@@ -26,3 +26,36 @@ pub async fn health(address: &str) -> Result<JsValue, JsValue> {
     )
     .into())
 }
+
+/// Call `on_change` with each change to stored files at or below `prefix`
+/// as it's made, passing the event as a plain object. Returns the
+/// `EventSource` delivering them; close it to stop watching.
+#[wasm_bindgen]
+pub fn watch(
+    address: &str,
+    prefix: &str,
+    on_change: web_sys::js_sys::Function,
+) -> Result<web_sys::EventSource, JsValue> {
+    let uri = format!(
+        "http://{}/files/watch?prefix={}",
+        address,
+        String::from(web_sys::js_sys::encode_uri_component(prefix))
+    );
+    let source = web_sys::EventSource::new(&uri)?;
+    let on_message =
+        Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |message: web_sys::MessageEvent| {
+            let Some(data) = message.data().as_string() else {
+                return;
+            };
+
+            if let Ok(event) = web_sys::js_sys::JSON::parse(&data) {
+                let _ = on_change.call1(&JsValue::NULL, &event);
+            }
+        });
+
+    source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    // the handler lives as long as the source, which outlives this call
+    on_message.forget();
+
+    Ok(source)
+}